@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+
+use logos::{Lexer, Logos};
+use colored::*;
+
+use crate::token::{number_error, Token, BUILT_IN_VALUES};
+use crate::value::Value;
+use crate::misc::error;
+
+// an array, from "array buf[32] in cell1 at 0" or "regs arr[8]"
+pub struct Array {
+    pub size: i64,
+    pub storage: Storage,
+}
+
+pub enum Storage {
+    Cell(String, i64), // a memory cell, and the address of the first element
+    Registers, // a variable for each element, like arr_0
+}
+
+// the variable for an element of a register array
+pub fn element(array: &str, index: i64) -> String {
+    format!("{}_{}", array, index)
+}
+
+// what goes between the brackets, either a number or a variable plus a number
+enum Index {
+    Constant(i64),
+    Variable(String, i64),
+}
+
+enum Kind {
+    Write, // buf[i] = x, the value's still left in the lexer
+    Read(String), // x = buf[i]
+}
+
+// one line that reads or writes an array
+pub struct Access {
+    array: String,
+    index: Index,
+    kind: Kind,
+}
+
+fn print_def() {
+    println!("{} arrays are declared as: array name[size] in cell (at address), or regs name[size]", "note:".bold());
+}
+
+fn print_access_def() {
+    println!("{} arrays are used as: name[index] = value, or variable = name[index], where index is a number or a variable plus or minus a number", "note:".bold());
+}
+
+// memory cells and banks only go so far
+fn capacity(cell: &str) -> Option<(i64, &str)> {
+    if cell.starts_with("cell") {
+        Some((64, "memory cells"))
+    } else if cell.starts_with("bank") {
+        Some((512, "memory banks"))
+    } else {
+        None
+    }
+}
+
+fn next_token(lex: &mut Lexer<Token>) -> Option<Token> {
+    lex.next().map(|token| token.as_name(lex.slice()))
+}
+
+fn expect(lex: &mut Lexer<Token>, expected: Token, description: &str, filename: &str, line: u32, def: fn()) {
+    if next_token(lex) != Some(expected) {
+        error(&format!("expected {}, got {}", description, lex.slice()), filename, line);
+        def();
+        std::process::exit(1);
+    }
+}
+
+// name[size], after array or regs
+fn parse_declaration(lex: &mut Lexer<Token>, filename: &str, line: u32) -> (String, i64) {
+    let name = match next_token(lex) {
+        Some(Token::Name(name)) => name,
+        _ => { error(&format!("expected array name, got {}", lex.slice()), filename, line); print_def(); std::process::exit(1); },
+    };
+    expect(lex, Token::LeftBracket, "[", filename, line, print_def);
+    let size = match next_token(lex) {
+        Some(Token::Int(size)) if size > 0 => size,
+        _ => { error(&format!("expected array size, got {}", lex.slice()), filename, line); print_def(); std::process::exit(1); },
+    };
+    expect(lex, Token::RightBracket, "]", filename, line, print_def);
+    (name, size)
+}
+
+fn declare(arrays: &mut HashMap<String, Array>, name: String, array: Array, filename: &str, line: u32) {
+    if arrays.contains_key(&name) {
+        error(&format!("array {} is declared more than once", name), filename, line);
+        std::process::exit(1);
+    }
+    arrays.insert(name, array);
+}
+
+// goes through everything before compiling, so arrays can be used anywhere in the file
+pub fn find_arrays(string: &str, filename: &str) -> HashMap<String, Array> {
+    let mut lex = Token::lexer(string);
+    let mut line_count = 1;
+    let mut line_start = true;
+    let mut arrays: HashMap<String, Array> = HashMap::new();
+
+    while let Some(token) = lex.next() {
+        let at_start = line_start;
+        line_start = matches!(token, Token::Newline);
+        match token {
+            Token::Newline => line_count += 1,
+            Token::Regs if at_start => {
+                let (name, size) = parse_declaration(&mut lex, filename, line_count);
+                declare(&mut arrays, name, Array { size, storage: Storage::Registers }, filename, line_count);
+                match next_token(&mut lex) {
+                    Some(Token::Newline) => { line_start = true; line_count += 1; },
+                    None => (),
+                    _ => { error(&format!("unexpected token {} after array", lex.slice()), filename, line_count); print_def(); std::process::exit(1); },
+                }
+            },
+            Token::Array if at_start => {
+                let (name, size) = parse_declaration(&mut lex, filename, line_count);
+                expect(&mut lex, Token::Name("in".to_string()), "in", filename, line_count, print_def);
+                let cell = match next_token(&mut lex) {
+                    Some(Token::Name(cell)) | Some(Token::SysVar(cell)) => cell,
+                    _ => { error(&format!("expected memory cell, got {}", lex.slice()), filename, line_count); print_def(); std::process::exit(1); },
+                };
+                let offset = match next_token(&mut lex) {
+                    Some(Token::Name(at)) if at == "at" => match next_token(&mut lex) {
+                        Some(Token::Int(offset)) if offset >= 0 => {
+                            match next_token(&mut lex) {
+                                Some(Token::Newline) => line_start = true,
+                                None => (),
+                                _ => { error(&format!("unexpected token {} after array", lex.slice()), filename, line_count); std::process::exit(1); },
+                            }
+                            offset
+                        },
+                        _ => { error(&format!("expected address, got {}", lex.slice()), filename, line_count); print_def(); std::process::exit(1); },
+                    },
+                    Some(Token::Newline) => { line_start = true; 0 },
+                    None => 0,
+                    _ => { error(&format!("unexpected token {} after array", lex.slice()), filename, line_count); print_def(); std::process::exit(1); },
+                };
+                if let Some((capacity, kind)) = capacity(&cell) {
+                    if offset + size > capacity {
+                        error(&format!("{} needs addresses up to {}, but {} only hold {}", name, offset + size - 1, kind, capacity), filename, line_count);
+                        std::process::exit(1);
+                    }
+                }
+                declare(&mut arrays, name, Array { size, storage: Storage::Cell(cell, offset) }, filename, line_count);
+                if line_start {
+                    line_count += 1;
+                }
+            },
+            _ => (),
+        }
+    }
+
+    arrays
+}
+
+// whether the line starting with this name is an array access, like buf[i] = x or x = buf[i]
+pub fn is_access(lex: &Lexer<Token>) -> bool {
+    matches!(lex.clone().next(), Some(Token::LeftBracket) | Some(Token::Assign))
+}
+
+// the part in brackets, after the [
+fn parse_index(lex: &mut Lexer<Token>, filename: &str, line: u32) -> Index {
+    fn fail(lex: &Lexer<Token>, filename: &str, line: u32) -> ! {
+        match number_error(lex) {
+            Some(message) => error(&message, filename, line),
+            None => error(&format!("unexpected {} in array index", lex.slice()), filename, line),
+        }
+        print_access_def();
+        std::process::exit(1);
+    }
+
+    let index = match next_token(lex) {
+        Some(Token::Int(number)) => Index::Constant(number),
+        Some(Token::Name(name)) => Index::Variable(name, 0),
+        _ => fail(lex, filename, line),
+    };
+    let index = match (next_token(lex), index) {
+        (Some(Token::RightBracket), index) => return index,
+        (Some(Token::Error), Index::Variable(name, _)) if lex.slice().starts_with('-') => match lex.slice().parse::<i64>() {
+            Ok(number) => Index::Variable(name, number), // i-1, which isn't a negative number anywhere else
+            Err(_) => fail(lex, filename, line),
+        },
+        (Some(Token::Plus), Index::Constant(base)) => match next_token(lex) {
+            Some(Token::Int(number)) => Index::Constant(base + number),
+            Some(Token::Name(name)) => Index::Variable(name, base),
+            _ => fail(lex, filename, line),
+        },
+        (Some(Token::Plus), Index::Variable(name, _)) => match next_token(lex) {
+            Some(Token::Int(number)) => Index::Variable(name, number),
+            Some(Token::Name(_)) => {
+                error("array indexes can't add two variables together, add them up with op first", filename, line);
+                print_access_def();
+                std::process::exit(1);
+            },
+            _ => fail(lex, filename, line),
+        },
+        (Some(Token::Minus), Index::Variable(name, _)) => match next_token(lex) {
+            Some(Token::Int(number)) => Index::Variable(name, -number),
+            _ => fail(lex, filename, line),
+        },
+        (Some(Token::Minus), Index::Constant(base)) => match next_token(lex) {
+            Some(Token::Int(number)) => Index::Constant(base - number),
+            _ => fail(lex, filename, line),
+        },
+        _ => fail(lex, filename, line),
+    };
+    expect(lex, Token::RightBracket, "]", filename, line, print_access_def);
+    index
+}
+
+fn parse_array(lex: &mut Lexer<Token>, name: String, arrays: &HashMap<String, Array>, filename: &str, line: u32) -> String {
+    if !arrays.contains_key(&name) {
+        error(&format!("{} isn't an array", name), filename, line);
+        print_def();
+        std::process::exit(1);
+    }
+    expect(lex, Token::LeftBracket, "[", filename, line, print_access_def);
+    name
+}
+
+impl Access {
+    // reads the line up to the value being written, or to the end for reads
+    pub fn parse(lex: &mut Lexer<Token>, first: String, arrays: &HashMap<String, Array>, filename: &str, line: u32) -> Access {
+        match lex.clone().next() {
+            Some(Token::Assign) => {
+                lex.next();
+                if BUILT_IN_VALUES.contains(&first.as_str()) {
+                    error(&format!("{} is a built in value, so it can't be used as a variable name", first), filename, line);
+                    std::process::exit(1);
+                }
+                let array = match next_token(lex) {
+                    Some(Token::Name(name)) => parse_array(lex, name, arrays, filename, line),
+                    _ => {
+                        error(&format!("expected an array to read from, got {} (use set for anything else)", lex.slice()), filename, line);
+                        print_access_def();
+                        std::process::exit(1);
+                    },
+                };
+                let index = parse_index(lex, filename, line);
+                Access { array, index, kind: Kind::Read(first) }
+            },
+            _ => {
+                let array = parse_array(lex, first, arrays, filename, line);
+                let index = parse_index(lex, filename, line);
+                expect(lex, Token::Assign, "=", filename, line, print_access_def);
+                Access { array, index, kind: Kind::Write }
+            },
+        }
+    }
+
+    // the variable a read declares
+    pub fn output(&self) -> Option<&String> {
+        match &self.kind {
+            Kind::Read(variable) => Some(variable),
+            Kind::Write => None,
+        }
+    }
+
+    // how many instructions it turns into, which pass one needs to know
+    pub fn count(&self, arrays: &HashMap<String, Array>, bounds_check: bool) -> u32 {
+        let array = &arrays[&self.array];
+        match &self.index {
+            Index::Constant(_) => 1,
+            Index::Variable(_, number) => {
+                let checks = if bounds_check { 2 } else { 0 };
+                match &array.storage {
+                    Storage::Cell(_, offset) => checks + if offset + number != 0 { 1 } else { 0 } + 1,
+                    // checking or clamping the index, working out the offset, jumping into the table, then a set and a
+                    // jump out for every element but the last
+                    Storage::Registers => 2 + if *number != 0 { 1 } else { 0 } + 2 + array.size as u32 * 2 - 1,
+                }
+            },
+        }
+    }
+
+    // address is where the code is going to start, and bounds_check is the address to jump to when the
+    // index is out of bounds, if they're being checked
+    #[allow(clippy::too_many_arguments)]
+    pub fn compile(&self, lex: &mut Lexer<Token>, arrays: &HashMap<String, Array>, variables: &[String], bounds_check: Option<u32>, address: usize, filename: &str, line: u32, debug: bool) -> Vec<String> {
+        let array = &arrays[&self.array];
+        let value = match &self.kind {
+            Kind::Write => match Value::Any("value".to_string()).compile(lex, variables, false, filename, line) {
+                Some(value) => Some(value),
+                None => { print_access_def(); std::process::exit(1); },
+            },
+            Kind::Read(_) => None,
+        };
+        let comment = if debug { format!(" # array {}", self.array) } else { String::new() };
+        let mut code = Vec::new();
+
+        let (name, number) = match &self.index {
+            Index::Constant(index) => {
+                if *index < 0 || *index >= array.size {
+                    error(&format!("index {} is out of bounds for {}, which has {} elements", index, self.array, array.size), filename, line);
+                    std::process::exit(1);
+                }
+                let access = match (&array.storage, &self.kind, &value) {
+                    (Storage::Cell(cell, offset), Kind::Read(variable), _) => format!("read {} {} {}", variable, cell, offset + index),
+                    (Storage::Cell(cell, offset), Kind::Write, Some(value)) => format!("write {} {} {}", value, cell, offset + index),
+                    (Storage::Registers, Kind::Read(variable), _) => format!("set {} {}", variable, element(&self.array, *index)),
+                    (Storage::Registers, Kind::Write, Some(value)) => format!("set {} {}", element(&self.array, *index), value),
+                    _ => unreachable!(),
+                };
+                return vec![access + &comment];
+            },
+            Index::Variable(name, number) => (name, *number),
+        };
+
+        if !variables.contains(name) {
+            error(&format!("{} isn't a variable (maybe you forgot to create it?)", name), filename, line);
+            std::process::exit(1);
+        }
+        // name + number has to be from 0 up to the size
+        if let Some(handler) = bounds_check {
+            code.push(format!("jump {} lessThan {} {}", handler, name, -number));
+            code.push(format!("jump {} greaterThanEq {} {}", handler, name, array.size - number));
+        }
+        match &array.storage {
+            Storage::Cell(cell, offset) => {
+                let address = if offset + number != 0 {
+                    code.push(format!("op add {}Address {} {}", self.array, name, offset + number));
+                    format!("{}Address", self.array)
+                } else {
+                    name.to_string()
+                };
+                code.push(match &value {
+                    Some(value) => format!("write {} {} {}{}", value, cell, address, comment),
+                    None => format!("read {} {} {}{}", self.output().unwrap(), cell, address, comment),
+                });
+            },
+            Storage::Registers => {
+                // each element gets two instructions in the table, so jump forward twice the index
+                let index = format!("{}Index", self.array);
+                let mut source = name.to_string();
+                if number != 0 {
+                    code.push(format!("op add {} {} {}", index, name, number));
+                    source = index.clone();
+                }
+                // without a bounds check an index past either end would jump out of the table into whatever code
+                // is around it, so clamp it to the first or last element instead
+                if bounds_check.is_none() {
+                    code.push(format!("op max {} {} 0", index, source));
+                    code.push(format!("op min {} {} {}", index, index, array.size - 1));
+                    source = index.clone();
+                }
+                code.push(format!("op mul {} {} 2", index, source));
+                code.push(format!("op add @counter @counter {}{}", index, comment));
+                let end = address + self.count(arrays, bounds_check.is_some()) as usize;
+                for element_index in 0..array.size {
+                    code.push(match &value {
+                        Some(value) => format!("set {} {}", element(&self.array, element_index), value),
+                        None => format!("set {} {}", self.output().unwrap(), element(&self.array, element_index)),
+                    });
+                    if element_index < array.size - 1 {
+                        code.push(format!("jump {} always", end));
+                    }
+                }
+            },
+        }
+        code
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// every name the game knows about, sorted into categories, see the top of the file for how it's laid out
+const BUNDLED_CONTENT: &str = include_str!("content.toml");
+
+pub struct ContentDatabase {
+    categories: HashMap<String, Vec<String>>,
+}
+
+// loaded the first time it's needed
+pub fn database() -> &'static ContentDatabase {
+    static DATABASE: OnceLock<ContentDatabase> = OnceLock::new();
+    DATABASE.get_or_init(|| ContentDatabase {
+        categories: toml::from_str(BUNDLED_CONTENT).expect("the bundled content database is broken"),
+    })
+}
+
+// how many single character changes it takes to turn one string into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            current.push((previous[j] + (a != *b) as usize).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+impl ContentDatabase {
+    // everything in a category with the categories it includes filled in, None if there's no such category
+    pub fn names(&self, category: &str) -> Option<Vec<&str>> {
+        let entries = self.categories.get(category)?;
+        let mut names = Vec::new();
+        for entry in entries.iter() {
+            match self.names(entry) {
+                Some(included) => names.extend(included),
+                None => names.push(entry.as_str()),
+            }
+        }
+        Some(names)
+    }
+
+    // whether a name fits a category, and if it doesn't, the closest one that does (if any are close).
+    // categories the database doesn't know about take anything
+    pub fn check(&self, category: &str, name: &str) -> Result<(), Option<&str>> {
+        let names = match self.names(category) {
+            Some(names) => names,
+            None => return Ok(()),
+        };
+        if names.contains(&name) || self.categories["Variables"].iter().any(|variable| variable == name) {
+            return Ok(());
+        }
+
+        // case and a missing @ are the usual mistakes, so they don't count against a match
+        let simplify = |name: &str| name.trim_start_matches('@').to_lowercase();
+        let closest = names.iter()
+            .map(|candidate| (edit_distance(&simplify(name), &simplify(candidate)), *candidate))
+            .min();
+        match closest {
+            Some((distance, candidate)) if distance <= (name.len() / 3).max(2) => Err(Some(candidate)),
+            _ => Err(None),
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use colored::*;
+
+use crate::Compiled;
+use crate::interpreter::{Object, Processor, Status};
+
+const HELP: &str = "commands:
+  break <line>     stop when the program reaches a line (b)
+  delete [line]    remove a breakpoint, or all of them (d)
+  step [count]     run one instruction, or count of them (s)
+  continue         run until a breakpoint or the end of the program (c)
+  print <name>     show the value of a variable (p)
+  set <name> <value>
+                   change a variable (or @counter)
+  vars             show every variable
+  cell <name>      show what's in a memory cell or bank
+  where            show where the program is (w)
+  quit             exit the debugger (q)
+an empty line repeats the last command";
+
+// the routine and nearest label before an address, for showing where the program's at. only labels in the
+// same routine (or the main program) count, since one further up belongs to some other code
+fn location(compiled: &Compiled, address: usize) -> String {
+    let mut parts = Vec::new();
+    let scope = compiled.scopes.get(address);
+    if let Some(Some(routine)) = scope {
+        parts.push(format!("routine {}", routine));
+    }
+    let label = compiled.labels.iter()
+        .filter(|(_, label_address)| **label_address as usize <= address)
+        .filter(|(_, label_address)| compiled.scopes.get(**label_address as usize..=address).is_some_and(|between| between.iter().all(|other| Some(other) == scope)))
+        .max_by_key(|(name, label_address)| (**label_address, name.to_string()));
+    if let Some((name, label_address)) = label {
+        let offset = address - *label_address as usize;
+        if offset == 0 {
+            parts.push(format!("label {}", name));
+        } else {
+            parts.push(format!("label {}+{}", name, offset));
+        }
+    }
+    parts.join(", ")
+}
+
+fn show_position(compiled: &Compiled, processor: &Processor, source: &[&str]) {
+    let address = processor.counter;
+    let line = compiled.lines.get(address).copied().unwrap_or(0);
+    let location = location(compiled, address);
+    println!(
+        "{} {}{}",
+        format!("@counter {}, line {}", address, line).bold(),
+        if location.is_empty() { "".to_string() } else { format!("({}) ", location) },
+        compiled.code.get(address).map(|code| code.as_str()).unwrap_or("")
+    );
+    if let Some(text) = source.get((line as usize).wrapping_sub(1)) {
+        println!("    {}", text.trim());
+    }
+}
+
+// runs one instruction, returns false if the program should stop here
+fn step(compiled: &Compiled, processor: &mut Processor) -> bool {
+    let address = processor.counter;
+    match processor.step() {
+        Ok(Status::Running) => true,
+        Ok(Status::Ended) => {
+            println!("program ended, continuing will start it over from the top");
+            false
+        },
+        Ok(Status::Stopped) => {
+            println!("program stopped, it won't go any further");
+            false
+        },
+        Ok(Status::AssertionFailed(message)) | Err(message) => {
+            println!("{} {} (line {})", "error:".red().bold(), message, compiled.lines[address]);
+            false
+        },
+    }
+}
+
+// an interactive debugger for a compiled program, reading commands from stdin
+pub fn debug(compiled: &Compiled, processor: &mut Processor, string: &str, budget: u64) {
+    let source: Vec<&str> = string.lines().collect();
+    let mut breakpoints: BTreeSet<u32> = BTreeSet::new();
+    let mut last_command = String::new();
+
+    println!("type \"help\" for a list of commands");
+    show_position(compiled, processor, &source);
+
+    let stdin = io::stdin();
+    loop {
+        print!("(mlogp) ");
+        io::stdout().flush().unwrap();
+
+        let mut input = String::new();
+        if stdin.lock().read_line(&mut input).unwrap_or(0) == 0 {
+            println!();
+            break;
+        }
+        let input = if input.trim().is_empty() { last_command.clone() } else { input.trim().to_string() };
+        last_command = input.clone();
+
+        let words: Vec<&str> = input.split_whitespace().collect();
+        match words.as_slice() {
+            [] => (),
+            ["help" | "h"] => println!("{}", HELP),
+            ["quit" | "q"] => break,
+            ["break" | "b", line] => {
+                let line: u32 = match line.parse() {
+                    Ok(line) => line,
+                    Err(_) => { println!("expected a line number, got {}", line); continue; },
+                };
+                // breakpoints on lines without code go on the next line that has some
+                match compiled.lines.iter().filter(|code_line| **code_line >= line).min() {
+                    Some(code_line) => {
+                        breakpoints.insert(*code_line);
+                        println!("breakpoint set on line {}", code_line);
+                    },
+                    None => println!("there's no code on or after line {}", line),
+                }
+            },
+            ["delete" | "d"] => {
+                breakpoints.clear();
+                println!("deleted all breakpoints");
+            },
+            ["delete" | "d", line] => {
+                if line.parse().map(|line: u32| breakpoints.remove(&line)).unwrap_or(false) {
+                    println!("deleted breakpoint on line {}", line);
+                } else {
+                    println!("no breakpoint on line {}", line);
+                }
+            },
+            ["step" | "s", rest @ ..] => {
+                let count: u64 = match rest.first().map(|count| count.parse()) {
+                    None => 1,
+                    Some(Ok(count)) => count,
+                    Some(Err(_)) => { println!("expected a number of instructions to step"); continue; },
+                };
+                for _ in 0..count {
+                    if !step(compiled, processor) {
+                        break;
+                    }
+                }
+                show_position(compiled, processor, &source);
+            },
+            ["continue" | "c"] => {
+                let start = processor.executed;
+                loop {
+                    if !step(compiled, processor) {
+                        break;
+                    }
+                    if breakpoints.contains(&compiled.lines[processor.counter]) {
+                        println!("hit breakpoint on line {}", compiled.lines[processor.counter]);
+                        break;
+                    }
+                    if processor.executed - start >= budget {
+                        println!("stopped after {} instructions without hitting a breakpoint", budget);
+                        break;
+                    }
+                }
+                show_position(compiled, processor, &source);
+            },
+            ["print" | "p", name] => println!("{} = {}", name, processor.get(name)),
+            ["set", name, value] => {
+                let value = processor.get(value);
+                processor.set(name, value);
+                if *name == "@counter" {
+                    // out of range addresses wrap back to the start, same as they would when running
+                    if processor.counter >= processor.program.len() {
+                        processor.counter = 0;
+                    }
+                    show_position(compiled, processor, &source);
+                } else {
+                    println!("{} = {}", name, processor.get(name));
+                }
+            },
+            ["vars"] => {
+                let mut names: Vec<&String> = processor.variables.keys().collect();
+                names.sort();
+                for name in names {
+                    println!("{} = {}", name, processor.variables[name]);
+                }
+            },
+            ["cell", name] => {
+                match processor.memory.get(*name) {
+                    Some(memory) => {
+                        for (row, values) in memory.chunks(8).enumerate() {
+                            let values: Vec<String> = values.iter().map(|value| format!("{:>8}", Object::Number(*value).to_string())).collect();
+                            println!("{:>4}: {}", row * 8, values.join(" "));
+                        }
+                    },
+                    None => println!("{} hasn't been read from or written to", name),
+                }
+            },
+            ["where" | "w"] => show_position(compiled, processor, &source),
+            _ => println!("unknown command \"{}\", type \"help\" for a list of commands", input),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // main is 0-3 with a label at 2, then routine fn at 4-7 with a label at 6
+    fn compiled() -> Compiled {
+        let main = vec![None; 4];
+        let routine = vec![Some("fn".to_string()); 4];
+        Compiled {
+            code: vec!["noop".to_string(); 8],
+            lines: (1..=8).collect(),
+            scopes: main.into_iter().chain(routine).collect(),
+            labels: HashMap::from([("loop".to_string(), 2), ("inner".to_string(), 6)]),
+            routines: HashMap::from([("fn".to_string(), 4)]),
+        }
+    }
+
+    #[test]
+    fn labels_in_the_main_program() {
+        let compiled = compiled();
+        assert_eq!(location(&compiled, 1), "");
+        assert_eq!(location(&compiled, 2), "label loop");
+        assert_eq!(location(&compiled, 3), "label loop+1");
+    }
+
+    #[test]
+    fn labels_stay_inside_their_routine() {
+        let compiled = compiled();
+        // loop is closer than anything in fn, but it's in the main program
+        assert_eq!(location(&compiled, 4), "routine fn");
+        assert_eq!(location(&compiled, 5), "routine fn");
+        assert_eq!(location(&compiled, 7), "routine fn, label inner+1");
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::{HashMap, HashSet};
+
+use logos::Logos;
+
+use crate::token::Token;
+use crate::instruction_compiler::InstructionCompiler;
+use crate::misc::{error, warning, split_line};
+
+// comparisons jump understands, anything else is a mistake
+const COMPARISONS: [&str; 8] = ["equal", "notEqual", "lessThan", "lessThanEq", "greaterThan", "greaterThanEq", "strictEqual", "always"];
+
+// a single line of mlog, split up into its arguments
+struct RawInstruction {
+    words: Vec<String>,
+    line: u32,
+}
+
+impl RawInstruction {
+    fn jump_target(&self) -> Option<usize> {
+        if self.words[0] == "jump" {
+            self.words[1].parse().ok()
+        } else {
+            None
+        }
+    }
+}
+
+// checks an instruction against the instruction list, trimming off any of the extra arguments the game likes to add
+fn validate(words: &mut Vec<String>, instructions: &[Box<dyn InstructionCompiler>], filename: &str, line: u32) {
+    let mut defaults: &[Option<String>] = &[]; // for the arguments, which can fill in ones that were left off
+    let expected = match words[0].as_str() {
+        "jump" => {
+            if words.len() < 3 {
+                error("expected jump target and comparison", filename, line);
+                std::process::exit(1);
+            }
+            if words[1].parse::<usize>().is_err() {
+                error(&format!("expected jump target, got {}", words[1]), filename, line);
+                std::process::exit(1);
+            }
+            if !COMPARISONS.contains(&words[2].as_str()) {
+                error(&format!("unknown comparison {}", words[2]), filename, line);
+                std::process::exit(1);
+            }
+            if words[2] == "always" { 3 } else { 5 }
+        },
+        "op" => {
+            if words.len() < 2 || !matches!(Token::lexer(&words[1]).next(), Some(Token::Op(_))) {
+                error(&format!("unknown op {}", words.get(1).map(|s| s.as_str()).unwrap_or("")), filename, line);
+                std::process::exit(1);
+            }
+            5
+        },
+        name => {
+            let mut expected = None;
+            for inst in instructions.iter() {
+                if let Some(inst) = inst.as_instruction() {
+                    if inst.name == name {
+                        expected = Some(1 + inst.arguments.len());
+                        defaults = &inst.defaults;
+                        break;
+                    }
+                } else if let Some(group) = inst.as_group() {
+                    if group.name == name {
+                        let sub_name = match words.get(1) {
+                            Some(sub_name) => sub_name,
+                            None => { error(&format!("expected sub-instruction name after \"{}\"", name), filename, line); std::process::exit(1); },
+                        };
+                        if let Some(sub) = group.sub_instructions.iter().find(|sub| &sub.name == sub_name) {
+                            expected = Some(2 + sub.arguments.len());
+                            defaults = &sub.defaults;
+                        } else if let Some(sub) = group.unavailable.iter().find(|sub| &sub.name == sub_name) {
+                            error(&sub.message, filename, line);
+                            std::process::exit(1);
+                        } else {
+                            error(&format!("unknown sub-instruction {} for \"{}\"", sub_name, name), filename, line);
+                            std::process::exit(1);
+                        }
+                        break;
+                    }
+                } else if let Some(unavailable) = inst.as_unavailable() {
+                    if unavailable.name == name {
+                        error(&unavailable.message, filename, line);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            match expected {
+                Some(expected) => expected,
+                None => { error(&format!("unknown instruction {}", name), filename, line); std::process::exit(1); },
+            }
+        },
+    };
+
+    let first_argument = expected - defaults.len();
+    while words.len() < expected {
+        match words.len().checked_sub(first_argument).and_then(|index| defaults.get(index)) {
+            Some(Some(default)) => words.push(default.to_string()),
+            _ => break,
+        }
+    }
+    if words.len() < expected {
+        error(&format!("expected {} arguments for {}, got {}", expected - 1, words[0], words.len() - 1), filename, line);
+        std::process::exit(1);
+    }
+    if words.len() > expected {
+        // the game always writes out every argument, even ones the instruction doesn't use
+        let extra = words.split_off(expected);
+        if words[0] != "jump" && extra.iter().any(|word| word != "0" && word != "null" && word != "false") {
+            warning(&format!("dropping extra arguments \"{}\" from {}", extra.join(" "), words[0]), filename, line);
+        }
+    }
+}
+
+// turns plain mlog back into mlogp, with labels in place of addresses and subroutines where it can spot them
+pub fn decompile(string: &str, instructions: &[Box<dyn InstructionCompiler>], filename: &str) -> Vec<String> {
+    let mut program: Vec<RawInstruction> = Vec::new();
+    for (index, line) in string.lines().enumerate() {
+        let mut words = split_line(line);
+        if words.is_empty() {
+            continue;
+        }
+        validate(&mut words, instructions, filename, index as u32 + 1);
+        for word in words.iter_mut() {
+            // packed colors go back to color literals
+            if let Some(hex) = word.strip_prefix('%').filter(|hex| matches!(hex.len(), 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())) {
+                *word = format!("#{}", hex);
+            }
+        }
+        program.push(RawInstruction { words, line: index as u32 + 1 });
+    }
+
+    for inst in program.iter() {
+        if let Some(target) = inst.jump_target() {
+            if target > program.len() {
+                error(&format!("jump target {} is past the end of the program", target), filename, inst.line);
+                std::process::exit(1);
+            }
+        }
+    }
+    let all_targets: HashSet<usize> = program.iter().filter_map(|inst| inst.jump_target()).collect();
+
+    // find gosubs: "set nameReturn <address after jump>" followed by a jump to the routine
+    let mut calls: HashMap<usize, String> = HashMap::new(); // address of the set -> routine name
+    let mut starts: HashMap<String, Option<usize>> = HashMap::new();
+    for (addr, inst) in program.iter().enumerate() {
+        if inst.words[0] != "set" || !inst.words[1].ends_with("Return") || inst.words[1].len() == "Return".len() {
+            continue;
+        }
+        if inst.words[2].parse::<usize>().ok() != Some(addr + 2) || all_targets.contains(&(addr + 1)) {
+            continue;
+        }
+        if let Some(target) = program.get(addr + 1).and_then(|next| next.jump_target()) {
+            let name = inst.words[1][..inst.words[1].len() - "Return".len()].to_string();
+            let start = starts.entry(name.clone()).or_insert(Some(target));
+            if *start != Some(target) {
+                *start = None; // called with different targets, so it's not one of ours
+            }
+            calls.insert(addr, name);
+        }
+    }
+
+    // only count it as a routine if it's ever returned from
+    let returns_from = |name: &str, addr: usize| program[addr].words[0] == "set" && program[addr].words[1] == "@counter" && program[addr].words[2] == format!("{}Return", name);
+    let mut routines: Vec<(usize, String)> = starts.iter()
+        .filter_map(|(name, start)| start.map(|start| (start, name.clone())))
+        .filter(|(_, name)| (0..program.len()).any(|addr| returns_from(name, addr)))
+        .collect();
+    routines.sort();
+    calls.retain(|_, name| routines.iter().any(|(_, routine)| routine == name));
+
+    // each routine runs up to its last return before the next one starts
+    let mut routine_ranges: Vec<(usize, usize, String)> = Vec::new();
+    for (index, (start, name)) in routines.iter().enumerate() {
+        let next_start = routines.get(index + 1).map(|(start, _)| *start).unwrap_or(program.len());
+        let end = (*start..next_start).rev().find(|addr| returns_from(name, *addr)).map(|addr| addr + 1).unwrap_or(next_start);
+        routine_ranges.push((*start, end, name.clone()));
+    }
+
+    let mut labels: HashSet<usize> = HashSet::new();
+    for (addr, inst) in program.iter().enumerate() {
+        if let Some(target) = inst.jump_target() {
+            if addr == 0 || !calls.contains_key(&(addr - 1)) {
+                labels.insert(target);
+            }
+        }
+    }
+
+    let mut code: Vec<String> = Vec::new();
+    let mut current_routine: Option<&str> = None;
+    let mut addr = 0;
+    while addr <= program.len() {
+        if let Some((_, _, name)) = routine_ranges.iter().find(|(_, end, _)| *end == addr) {
+            if current_routine == Some(name.as_str()) {
+                code.push("endroutine".to_string());
+                current_routine = None;
+            }
+        }
+        if let Some((_, _, name)) = routine_ranges.iter().find(|(start, _, _)| *start == addr) {
+            if !code.is_empty() {
+                code.push("".to_string());
+            }
+            code.push(format!("routine {}:", name));
+            current_routine = Some(name);
+        }
+        let indent = if current_routine.is_some() { "    " } else { "" };
+        if labels.contains(&addr) {
+            code.push(format!("{}label{}:", indent, addr));
+        }
+        if addr == program.len() {
+            break;
+        }
+
+        let words = &program[addr].words;
+        if let Some(name) = calls.get(&addr) {
+            let jump = &program[addr + 1].words;
+            if jump[2] == "always" {
+                code.push(format!("{}gosub {}", indent, name));
+            } else {
+                code.push(format!("{}gosubc {} {}", indent, name, jump[2..].join(" ")));
+            }
+            addr += 2;
+            continue;
+        }
+        if let Some(target) = program[addr].jump_target() {
+            if words[2] == "always" {
+                code.push(format!("{}goto label{}", indent, target));
+            } else {
+                code.push(format!("{}jump label{} {}", indent, target, words[2..].join(" ")));
+            }
+        } else if current_routine.map(|name| returns_from(name, addr)).unwrap_or(false) {
+            code.push(format!("{}return", indent));
+        } else {
+            code.push(format!("{}{}", indent, words.join(" ")));
+        }
+        addr += 1;
+    }
+
+    code
+}
@@ -0,0 +1,170 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+// the game won't hold on to more draw instructions than this between flushes
+pub const MAX_DRAW_BUFFER: usize = 256;
+
+// where rendered displays get saved to
+pub struct DrawOutput {
+    pub directory: String,
+    pub frames: bool, // save every flush as its own numbered png instead of overwriting the last one
+}
+
+// a logic display, drawn into a framebuffer
+pub struct Display {
+    pub size: usize,
+    pub pixels: Vec<[u8; 4]>,
+    pub flushes: usize,
+    color: [u8; 4],
+    stroke: f64,
+}
+
+impl Display {
+    pub fn new(size: usize) -> Display {
+        Display {
+            size,
+            pixels: vec![[0, 0, 0, 255]; size * size],
+            flushes: 0,
+            color: [255, 255, 255, 255],
+            stroke: 1.0,
+        }
+    }
+
+    // blends the current color into a pixel, (0, 0) is the bottom left like in game
+    fn plot(&mut self, x: i64, y: i64) {
+        if x < 0 || y < 0 || x >= self.size as i64 || y >= self.size as i64 {
+            return;
+        }
+        let pixel = &mut self.pixels[y as usize * self.size + x as usize];
+        let alpha = self.color[3] as f64 / 255.0;
+        for (channel, color) in pixel.iter_mut().zip(self.color.iter()).take(3) {
+            *channel = (*color as f64 * alpha + *channel as f64 * (1.0 - alpha)).round() as u8;
+        }
+    }
+
+    // fills every pixel whose center passes the test, only looking inside the given bounds
+    fn fill(&mut self, bounds: (f64, f64, f64, f64), inside: impl Fn(f64, f64) -> bool) {
+        let (min_x, min_y, max_x, max_y) = bounds;
+        let max = self.size as f64;
+        for y in min_y.max(0.0).floor() as i64..max_y.min(max).ceil() as i64 {
+            for x in min_x.max(0.0).floor() as i64..max_x.min(max).ceil() as i64 {
+                if inside(x as f64 + 0.5, y as f64 + 0.5) {
+                    self.plot(x, y);
+                }
+            }
+        }
+    }
+
+    fn line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64) {
+        let radius = self.stroke / 2.0;
+        let bounds = (x1.min(x2) - radius, y1.min(y2) - radius, x1.max(x2) + radius, y1.max(y2) + radius);
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        let length = dx * dx + dy * dy;
+        self.fill(bounds, |x, y| {
+            let t = if length == 0.0 { 0.0 } else { (((x - x1) * dx + (y - y1) * dy) / length).clamp(0.0, 1.0) };
+            (x - (x1 + t * dx)).hypot(y - (y1 + t * dy)) <= radius
+        });
+    }
+
+    fn polygon(&mut self, points: &[(f64, f64)]) {
+        let bounds = points.iter().fold((f64::MAX, f64::MAX, f64::MIN, f64::MIN), |(min_x, min_y, max_x, max_y), (x, y)| {
+            (min_x.min(*x), min_y.min(*y), max_x.max(*x), max_y.max(*y))
+        });
+        // convex polygons only, a point's inside if it's on the same side of every edge
+        self.fill(bounds, |x, y| {
+            let mut sides = (false, false);
+            for i in 0..points.len() {
+                let (ax, ay) = points[i];
+                let (bx, by) = points[(i + 1) % points.len()];
+                let cross = (bx - ax) * (y - ay) - (by - ay) * (x - ax);
+                if cross > 0.0 {
+                    sides.0 = true;
+                } else if cross < 0.0 {
+                    sides.1 = true;
+                }
+            }
+            !(sides.0 && sides.1)
+        });
+    }
+
+    fn regular_polygon(x: f64, y: f64, sides: f64, radius: f64, rotation: f64) -> Vec<(f64, f64)> {
+        let sides = sides.max(3.0) as usize;
+        (0..sides).map(|i| {
+            let angle = (rotation + i as f64 * 360.0 / sides as f64).to_radians();
+            (x + angle.cos() * radius, y + angle.sin() * radius)
+        }).collect()
+    }
+
+    // runs a single draw instruction
+    pub fn draw(&mut self, command: &str, args: &[f64]) {
+        let arg = |index: usize| args.get(index).copied().unwrap_or(0.0);
+        let channel = |value: f64| value.clamp(0.0, 255.0) as u8;
+        match command {
+            "clear" => self.pixels.fill([channel(arg(0)), channel(arg(1)), channel(arg(2)), 255]),
+            "color" => self.color = [channel(arg(0)), channel(arg(1)), channel(arg(2)), if args.len() > 3 { channel(arg(3)) } else { 255 }],
+            "stroke" => self.stroke = arg(0),
+            "line" => self.line(arg(0), arg(1), arg(2), arg(3)),
+            "rect" => {
+                // a negative width or height goes left or down from x, y, like in game
+                let (x, y, w, h) = (arg(0), arg(1), arg(2), arg(3));
+                self.fill((x.min(x + w), y.min(y + h), x.max(x + w), y.max(y + h)), |_, _| true);
+            },
+            "lineRect" => {
+                let (x, y, w, h) = (arg(0), arg(1), arg(2), arg(3));
+                self.line(x, y, x + w, y);
+                self.line(x + w, y, x + w, y + h);
+                self.line(x + w, y + h, x, y + h);
+                self.line(x, y + h, x, y);
+            },
+            "poly" => self.polygon(&Display::regular_polygon(arg(0), arg(1), arg(2), arg(3), arg(4))),
+            "linePoly" => {
+                let points = Display::regular_polygon(arg(0), arg(1), arg(2), arg(3), arg(4));
+                for i in 0..points.len() {
+                    let ((x1, y1), (x2, y2)) = (points[i], points[(i + 1) % points.len()]);
+                    self.line(x1, y1, x2, y2);
+                }
+            },
+            "triangle" => self.polygon(&[(arg(0), arg(1)), (arg(2), arg(3)), (arg(4), arg(5))]),
+            _ => (), // images need the game's sprites, so they're skipped
+        }
+    }
+
+    pub fn save_png(&self, filename: &str) -> Result<(), String> {
+        let file = File::create(filename).map_err(|err| format!("couldn't create {}: {}", filename, err))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.size as u32, self.size as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        // pngs go top to bottom, displays go bottom to top
+        let mut data = Vec::with_capacity(self.pixels.len() * 4);
+        for row in self.pixels.chunks(self.size).rev() {
+            for pixel in row.iter() {
+                data.extend_from_slice(pixel);
+            }
+        }
+
+        let mut writer = encoder.write_header().map_err(|err| format!("couldn't write {}: {}", filename, err))?;
+        writer.write_image_data(&data).map_err(|err| format!("couldn't write {}: {}", filename, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the pixels a draw command turns white
+    fn drawn(command: &str, args: &[f64]) -> Vec<usize> {
+        let mut display = Display::new(8);
+        display.draw(command, args);
+        display.pixels.iter().enumerate().filter(|(_, pixel)| pixel[0] == 255).map(|(index, _)| index).collect()
+    }
+
+    #[test]
+    fn negative_rects_are_flipped() {
+        let expected = drawn("rect", &[2.0, 3.0, 3.0, 2.0]);
+        assert_eq!(expected.len(), 6);
+        assert_eq!(drawn("rect", &[5.0, 3.0, -3.0, 2.0]), expected);
+        assert_eq!(drawn("rect", &[2.0, 5.0, 3.0, -2.0]), expected);
+        assert_eq!(drawn("rect", &[5.0, 5.0, -3.0, -2.0]), expected);
+    }
+}
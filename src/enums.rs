@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use logos::{Lexer, Logos};
+use colored::*;
+
+use crate::token::Token;
+use crate::misc::error;
+
+// enum State { Idle, Mining, Returning }
+// makes State.Idle 0, State.Mining 1 and State.Returning 2. the lexer turns members straight into numbers,
+// so they work anywhere a number does
+
+#[derive(Default)]
+pub struct Enums {
+    members: HashMap<String, Vec<String>>,
+}
+
+impl Enums {
+    // "State.Mining" -> 1
+    pub fn value(&self, member: &str) -> Option<i64> {
+        let (name, member) = member.split_once('.')?;
+        self.members.get(name)?.iter().position(|other| other == member).map(|index| index as i64)
+    }
+
+    // the enum a member like "State.Mining" belongs to, and all of its members
+    pub fn enum_of(&self, member: &str) -> Option<(&str, &[String])> {
+        self.value(member)?;
+        let (name, _) = member.split_once('.')?;
+        self.members.get_key_value(name).map(|(name, members)| (name.as_str(), members.as_slice()))
+    }
+}
+
+fn print_def() {
+    println!("{} enums are declared as: enum Name {{ First, Second, Third }}", "note:".bold());
+}
+
+fn parse_declaration(lex: &mut Lexer<Token>, filename: &str, line_count: &mut u32) -> (String, Vec<String>) {
+    let start = *line_count;
+    let name = match lex.next().map(|token| token.as_name(lex.slice())) {
+        Some(Token::Name(name)) => name,
+        _ => { error(&format!("expected enum name, got {}", lex.slice()), filename, start); print_def(); std::process::exit(1); },
+    };
+    if lex.next() != Some(Token::LeftBrace) {
+        error(&format!("expected {{ after enum {}, got {}", name, lex.slice()), filename, start);
+        print_def();
+        std::process::exit(1);
+    }
+
+    // members are split up by commas or new lines
+    let mut members: Vec<String> = Vec::new();
+    let mut separated = true;
+    loop {
+        match lex.next().map(|token| token.as_name(lex.slice())) {
+            Some(Token::RightBrace) => break,
+            Some(Token::Newline) => { *line_count += 1; separated = true; },
+            Some(Token::Comma) if !separated => separated = true,
+            Some(Token::Name(member)) if separated => {
+                if members.contains(&member) {
+                    error(&format!("{} is in enum {} more than once", member, name), filename, *line_count);
+                    std::process::exit(1);
+                }
+                members.push(member);
+                separated = false;
+            },
+            Some(_) => { error(&format!("unexpected token {} in enum {}", lex.slice(), name), filename, *line_count); print_def(); std::process::exit(1); },
+            None => { error(&format!("enum {} is missing a }}", name), filename, start); print_def(); std::process::exit(1); },
+        }
+    }
+    if members.is_empty() {
+        error(&format!("enum {} doesn't have any members", name), filename, start);
+        std::process::exit(1);
+    }
+    if !matches!(lex.clone().next(), Some(Token::Newline) | None) {
+        lex.next();
+        error(&format!("unexpected token {} after enum {}", lex.slice(), name), filename, *line_count);
+        std::process::exit(1);
+    }
+    (name, members)
+}
+
+// finds every enum in a file, so members can be used before they're declared
+pub fn find_enums(string: &str, filename: &str) -> Rc<Enums> {
+    let mut lex = Token::lexer(string);
+    let mut line_count = 1;
+    let mut line_start = true;
+    let mut enums = Enums::default();
+
+    while let Some(token) = lex.next() {
+        let at_start = line_start;
+        line_start = matches!(token, Token::Newline);
+        match token {
+            Token::Newline => line_count += 1,
+            Token::Enum if at_start => {
+                let line = line_count;
+                let (name, members) = parse_declaration(&mut lex, filename, &mut line_count);
+                if enums.members.contains_key(&name) {
+                    error(&format!("there's already an enum called {}", name), filename, line);
+                    std::process::exit(1);
+                }
+                enums.members.insert(name, members);
+            },
+            _ => (),
+        }
+    }
+
+    let enums = Rc::new(enums);
+    check_members(string, &enums, filename);
+    enums
+}
+
+// anything written like a member that isn't one would just be an unexpected token later, so say what's wrong with it now
+fn check_members(string: &str, enums: &Rc<Enums>, filename: &str) {
+    let mut lex = Token::lexer_with_extras(string, enums.clone());
+    let mut line_count = 1;
+    while let Some(token) = lex.next() {
+        match token {
+            Token::Newline => line_count += 1,
+            Token::Error => {
+                if let Some(message) = unknown_member(enums, lex.slice()) {
+                    error(&message, filename, line_count);
+                    std::process::exit(1);
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+// what's wrong with something that didn't lex, if it looks like a member
+fn unknown_member(enums: &Enums, slice: &str) -> Option<String> {
+    let (name, member) = slice.split_once('.')?;
+    if name.is_empty() || member.is_empty() {
+        return None;
+    }
+    Some(match enums.members.contains_key(name) {
+        true => format!("enum {} doesn't have a member called {}", name, member),
+        false => format!("there's no enum called {}", name),
+    })
+}
+
+// skips over a declaration (already read by find_enums), giving how many lines it took up
+pub fn skip_declaration(lex: &mut Lexer<Token>) -> u32 {
+    let mut lines = 0;
+    for token in lex.by_ref() {
+        match token {
+            Token::RightBrace => break,
+            Token::Newline => lines += 1,
+            _ => (),
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    const SOURCE: &str = "enum State { Idle, Mining, Returning }\nenum Dir {\n    Up\n    Down,\n}\n";
+
+    fn tokens(enums: &Rc<Enums>, source: &str) -> Vec<Token> {
+        Token::lexer_with_extras(source, enums.clone()).collect()
+    }
+
+    #[test]
+    fn members_count_up_from_0() {
+        let enums = find_enums(SOURCE, "test");
+        assert_eq!(enums.value("State.Idle"), Some(0));
+        assert_eq!(enums.value("State.Returning"), Some(2));
+        // commas and new lines both split up members
+        assert_eq!(enums.value("Dir.Up"), Some(0));
+        assert_eq!(enums.value("Dir.Down"), Some(1));
+        assert_eq!(enums.enum_of("Dir.Down"), Some(("Dir", &["Up".to_string(), "Down".to_string()][..])));
+    }
+
+    #[test]
+    fn members_lex_as_ints() {
+        let enums = find_enums(SOURCE, "test");
+        assert_eq!(tokens(&enums, "s State.Mining"), vec![Token::Name("s".to_string()), Token::Int(1)]);
+        // without the enum it's nothing the lexer knows
+        assert_eq!(tokens(&Rc::new(Enums::default()), "State.Mining"), vec![Token::Error]);
+    }
+
+    #[test]
+    fn unknown_members_say_why() {
+        let enums = find_enums(SOURCE, "test");
+        assert_eq!(tokens(&enums, "State.Sleeping"), vec![Token::Error]);
+        assert_eq!(enums.value("State.Sleeping"), None);
+        assert_eq!(unknown_member(&enums, "State.Sleeping"), Some("enum State doesn't have a member called Sleeping".to_string()));
+        assert_eq!(unknown_member(&enums, "Mood.Happy"), Some("there's no enum called Mood".to_string()));
+        assert_eq!(unknown_member(&enums, ".5x"), None);
+    }
+
+    #[test]
+    fn members_go_where_numbers_go() {
+        let enums = find_enums(SOURCE, "test");
+        for value in [Value::Int("address".to_string()), Value::Any("value".to_string()), Value::Float("x".to_string())] {
+            let mut lex = Token::lexer_with_extras("State.Returning", enums.clone());
+            assert_eq!(value.compile(&mut lex, &[], false, "test", 1), Some("2".to_string()));
+        }
+        let mut lex = Token::lexer_with_extras("Dir.Down", enums.clone());
+        assert_eq!(Value::String("text".to_string()).compile(&mut lex, &[], false, "test", 1), None);
+    }
+
+    #[test]
+    fn declarations_are_skipped_whole() {
+        let mut lex = Token::lexer(SOURCE);
+        assert_eq!(lex.next(), Some(Token::Enum));
+        assert_eq!(skip_declaration(&mut lex), 0);
+        assert_eq!(lex.next(), Some(Token::Newline));
+        assert_eq!(lex.next(), Some(Token::Enum));
+        assert_eq!(skip_declaration(&mut lex), 3);
+    }
+}
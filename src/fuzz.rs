@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+
+use colored::*;
+
+use crate::compile;
+use crate::instruction_compiler::InstructionCompiler;
+use crate::interpreter::{Processor, Status};
+
+// how many variables generated programs get to play with
+const VARIABLES: usize = 4;
+
+// how many statements the reference evaluator runs before deciding a program doesn't end
+const REFERENCE_LIMIT: usize = 2000;
+
+// every label and routine starts with "set fuzzmark <id>", so they can be found in the emitted code
+// without trusting the addresses the compiler worked out. routines get ids starting here
+const ROUTINE_MARK: usize = 1000;
+
+const COMPARISONS: [&str; 6] = ["equal", "notEqual", "lessThan", "lessThanEq", "greaterThan", "greaterThanEq"];
+
+#[derive(Clone, Debug)]
+enum Statement {
+    Set(usize, i64),
+    Op(&'static str, usize, usize, i64),
+    Label(usize),
+    Jump(usize, Option<(&'static str, usize, i64)>), // no condition means goto
+    Gosub(usize, Option<(&'static str, usize, i64)>),
+    Return,
+}
+
+// a generated program, the main body followed by its routines
+#[derive(Clone)]
+struct Program {
+    main: Vec<Statement>,
+    routines: Vec<(usize, Vec<Statement>)>,
+}
+
+// plain xorshift, so a seed always gives the same programs
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, max: usize) -> usize {
+        (self.next() % max as u64) as usize
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+}
+
+fn comparison(condition: &Option<(&'static str, usize, i64)>) -> String {
+    match condition {
+        Some((comp, var, value)) => format!("{} v{} {}", comp, var, value),
+        None => "always".to_string(),
+    }
+}
+
+impl Program {
+    // makes up a random program, routines can only call routines after them so nothing recurses
+    fn generate(rng: &mut Rng, size: usize) -> Program {
+        let routine_count = rng.below(4);
+        let mut next_label = 0;
+
+        let mut body = |rng: &mut Rng, routine: Option<usize>| {
+            let length = rng.below(size) + 1;
+            let labels: Vec<usize> = (0..rng.below(4) + 1).map(|_| { next_label += 1; next_label - 1 }).collect();
+            let callable: Vec<usize> = (routine.map(|routine| routine + 1).unwrap_or(0)..routine_count).collect();
+
+            let mut statements = Vec::new();
+            for _ in 0..length {
+                let condition = if rng.chance(70) {
+                    Some((COMPARISONS[rng.below(COMPARISONS.len())], rng.below(VARIABLES), rng.below(11) as i64))
+                } else {
+                    None
+                };
+                statements.push(match rng.below(10) {
+                    0 | 1 => Statement::Set(rng.below(VARIABLES), rng.below(11) as i64),
+                    2..=4 => Statement::Op(if rng.chance(50) { "add" } else { "sub" }, rng.below(VARIABLES), rng.below(VARIABLES), rng.below(5) as i64),
+                    5 | 6 => Statement::Jump(labels[rng.below(labels.len())], condition),
+                    7 if !callable.is_empty() => Statement::Gosub(callable[rng.below(callable.len())], condition),
+                    8 if routine.is_some() && rng.chance(30) => Statement::Return,
+                    _ => Statement::Set(rng.below(VARIABLES), rng.below(11) as i64),
+                });
+            }
+            for label in labels {
+                statements.insert(rng.below(statements.len() + 1), Statement::Label(label));
+            }
+            statements
+        };
+
+        let main = body(rng, None);
+        let routines = (0..routine_count).map(|routine| (routine, body(rng, Some(routine)))).collect();
+        Program { main, routines }
+    }
+
+    fn render_statements(statements: &[Statement], lines: &mut Vec<String>) {
+        for statement in statements {
+            lines.push(match statement {
+                Statement::Set(var, value) => format!("set v{} {}", var, value),
+                Statement::Op(op, out, a, b) => format!("op {} v{} v{} {}", op, out, a, b),
+                Statement::Label(label) => format!("lbl{}:\nset fuzzmark {}", label, label),
+                Statement::Jump(label, None) => format!("goto lbl{}", label),
+                Statement::Jump(label, condition) => format!("jump lbl{} {}", label, comparison(condition)),
+                Statement::Gosub(routine, None) => format!("gosub sub{}", routine),
+                Statement::Gosub(routine, condition) => format!("gosubc sub{} {}", routine, comparison(condition)),
+                Statement::Return => "return".to_string(),
+            });
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut lines: Vec<String> = (0..VARIABLES).map(|var| format!("set v{} 0", var)).collect();
+        Program::render_statements(&self.main, &mut lines);
+        lines.push("end".to_string());
+        for (routine, statements) in self.routines.iter() {
+            lines.push(format!("routine sub{}:\nset fuzzmark {}", routine, ROUTINE_MARK + routine));
+            Program::render_statements(statements, &mut lines);
+            lines.push("return".to_string());
+            lines.push("endroutine".to_string());
+        }
+        lines.push("".to_string());
+        lines.join("\n")
+    }
+
+    // what the program should do: the marks it passes in order and the final values of its variables,
+    // or None if it doesn't end in time
+    fn evaluate(&self) -> Option<(Vec<usize>, Vec<f64>)> {
+        // flattened like the compiled program would be, with Statement::Return standing in for end after main
+        let mut flat: Vec<Statement> = self.main.clone();
+        flat.push(Statement::Return);
+        let mut routine_starts = HashMap::new();
+        for (routine, statements) in self.routines.iter() {
+            routine_starts.insert(*routine, flat.len());
+            flat.extend(statements.iter().cloned());
+            flat.push(Statement::Return);
+        }
+        let labels: HashMap<usize, usize> = flat.iter().enumerate()
+            .filter_map(|(index, statement)| if let Statement::Label(label) = statement { Some((*label, index)) } else { None })
+            .collect();
+
+        let mut vars = [0.0; VARIABLES];
+        let mut marks = Vec::new();
+        let mut stack: Vec<usize> = Vec::new();
+        let mut counter = 0;
+        let holds = |vars: &[f64; VARIABLES], condition: &Option<(&str, usize, i64)>| match condition {
+            Some((comp, var, value)) => {
+                let (a, b) = (vars[*var], *value as f64);
+                match *comp {
+                    "equal" => a == b,
+                    "notEqual" => a != b,
+                    "lessThan" => a < b,
+                    "lessThanEq" => a <= b,
+                    "greaterThan" => a > b,
+                    _ => a >= b,
+                }
+            },
+            None => true,
+        };
+
+        for _ in 0..REFERENCE_LIMIT {
+            let statement = flat[counter].clone();
+            counter += 1;
+            match statement {
+                Statement::Set(var, value) => vars[var] = value as f64,
+                Statement::Op(op, out, a, b) => vars[out] = if op == "add" { vars[a] + b as f64 } else { vars[a] - b as f64 },
+                Statement::Label(label) => marks.push(label),
+                Statement::Jump(label, condition) => {
+                    if holds(&vars, &condition) {
+                        counter = labels[&label];
+                    }
+                },
+                Statement::Gosub(routine, condition) => {
+                    if holds(&vars, &condition) {
+                        stack.push(counter);
+                        counter = routine_starts[&routine];
+                        marks.push(ROUTINE_MARK + routine);
+                    }
+                },
+                Statement::Return => match stack.pop() {
+                    Some(address) => counter = address,
+                    None => return Some((marks, vars.to_vec())),
+                },
+            }
+        }
+        None
+    }
+
+    // every way of making the program one statement smaller, dropping anything that used a removed label
+    fn shrink(&self) -> Vec<Program> {
+        let mut smaller = Vec::new();
+        let bodies = std::iter::once(&self.main).chain(self.routines.iter().map(|(_, statements)| statements)).enumerate();
+        for (body, statements) in bodies {
+            for index in 0..statements.len() {
+                let mut program = self.clone();
+                let removed = if body == 0 { program.main.remove(index) } else { program.routines[body - 1].1.remove(index) };
+                if let Statement::Label(label) = removed {
+                    program.retain(|statement| !matches!(statement, Statement::Jump(target, _) if *target == label));
+                }
+                smaller.push(program);
+            }
+        }
+        for index in 0..self.routines.len() {
+            let mut program = self.clone();
+            let (routine, _) = program.routines.remove(index);
+            program.retain(|statement| !matches!(statement, Statement::Gosub(target, _) if *target == routine));
+            smaller.push(program);
+        }
+        smaller
+    }
+
+    fn retain(&mut self, keep: impl Fn(&Statement) -> bool) {
+        self.main.retain(&keep);
+        for (_, statements) in self.routines.iter_mut() {
+            statements.retain(&keep);
+        }
+    }
+}
+
+fn mark_of(instruction: &str) -> Option<usize> {
+    instruction.strip_prefix("set fuzzmark ").and_then(|mark| mark.parse().ok())
+}
+
+// compiles a program and checks it against what it should do, returning what went wrong if anything
+fn check(program: &Program, instructions: &[Box<dyn InstructionCompiler>], name: &str) -> Option<String> {
+    let source = program.render();
+    let compiled = match compile(&source, name, instructions, false, None, None) {
+        Ok(compiled) => compiled,
+        Err(mismatch) => return Some(format!("{} (line {})", mismatch.message, mismatch.line)),
+    };
+
+    // where every label and routine actually ended up, going by the marks in the emitted code
+    let marks: HashMap<usize, usize> = compiled.code.iter().enumerate()
+        .filter_map(|(address, instruction)| mark_of(instruction).map(|mark| (mark, address)))
+        .collect();
+
+    for (label, address) in compiled.labels.iter() {
+        let expected = match label.strip_prefix("lbl").and_then(|label| label.parse::<usize>().ok()) {
+            Some(label) => marks[&label],
+            None => continue,
+        };
+        if *address as usize != expected {
+            return Some(format!("label {} was given address {}, but it's at {}", label, address, expected));
+        }
+    }
+    for (routine, address) in compiled.routines.iter() {
+        let expected = marks[&(ROUTINE_MARK + routine.trim_start_matches("sub").parse::<usize>().unwrap())];
+        if *address as usize != expected {
+            return Some(format!("routine {} was given address {}, but it's at {}", routine, address, expected));
+        }
+    }
+
+    // every jump has to land on the label (or routine) its line asked for
+    let source_lines: Vec<&str> = source.lines().collect();
+    for (address, instruction) in compiled.code.iter().enumerate() {
+        let words: Vec<&str> = instruction.split(' ').collect();
+        if words[0] != "jump" {
+            continue;
+        }
+        let line = source_lines[compiled.lines[address] as usize - 1];
+        let target = line.split(' ').nth(1).unwrap_or("");
+        let expected = if let Some(label) = target.strip_prefix("lbl") {
+            marks[&label.parse::<usize>().unwrap()]
+        } else if let Some(routine) = target.strip_prefix("sub") {
+            marks[&(ROUTINE_MARK + routine.parse::<usize>().unwrap())]
+        } else {
+            continue;
+        };
+        if words[1] != expected.to_string() {
+            return Some(format!("\"{}\" (line {}) jumps to {}, but {} is at {}", line, compiled.lines[address], words[1], target, expected));
+        }
+    }
+
+    // and running it has to go the same way as the reference
+    let (expected_marks, expected_vars) = program.evaluate()?;
+    let mut processor = Processor::new(&compiled.code);
+    let mut marks = Vec::new();
+    let budget = REFERENCE_LIMIT * 20;
+    for _ in 0..budget {
+        if let Some(mark) = compiled.code.get(processor.counter).and_then(|instruction| mark_of(instruction)) {
+            marks.push(mark);
+        }
+        match processor.step() {
+            Ok(Status::Running) => (),
+            Ok(Status::Ended | Status::Stopped) => break,
+            Ok(Status::AssertionFailed(message)) | Err(message) => return Some(format!("the interpreter failed: {}", message)),
+        }
+    }
+    if marks != expected_marks {
+        return Some(format!("passed labels {:?} when running, but should've passed {:?}", marks, expected_marks));
+    }
+    for (var, expected) in expected_vars.iter().enumerate() {
+        let value = processor.get(&format!("v{}", var)).num();
+        if value != *expected {
+            return Some(format!("v{} ended up as {}, but should be {}", var, value, expected));
+        }
+    }
+    None
+}
+
+// keeps taking statements out of a failing program for as long as it still fails
+fn minimize(mut program: Program, instructions: &[Box<dyn InstructionCompiler>], name: &str) -> (Program, String) {
+    let mut failure = check(&program, instructions, name).unwrap();
+    'outer: loop {
+        for smaller in program.shrink() {
+            if let Some(message) = check(&smaller, instructions, name) {
+                program = smaller;
+                failure = message;
+                continue 'outer;
+            }
+        }
+        return (program, failure);
+    }
+}
+
+// generates random programs and checks the compiler and interpreter agree on every one of them
+pub fn fuzz(instructions: &[Box<dyn InstructionCompiler>], seed: u64, count: usize, size: usize) -> bool {
+    let mut skipped = 0;
+    for index in 0..count as u64 {
+        let seed = seed.wrapping_add(index);
+        let mut rng = Rng(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1);
+        let program = Program::generate(&mut rng, size);
+        if program.evaluate().is_none() {
+            skipped += 1; // loops forever, so there's nothing to compare against
+            continue;
+        }
+
+        let name = format!("fuzz seed {}", seed);
+        if check(&program, instructions, &name).is_some() {
+            let (program, failure) = minimize(program, instructions, &name);
+            println!("{} {}", "error:".red().bold(), failure);
+            println!("{} seed {}, minimized program:", "note:".bold(), seed);
+            print!("{}", program.render());
+            return false;
+        }
+    }
+    println!("checked {} programs ({} skipped for not ending), no mismatches", count - skipped, skipped);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use logos::Lexer;
+
+    use crate::instruction_compiler::Instruction;
+    use crate::instruction_list::{create_instructions, Target};
+    use crate::token::Token;
+
+    // set, but compiled twice over, so everything after it ends up further along than pass one counted
+    struct DoubledSet(Box<dyn InstructionCompiler>);
+
+    impl InstructionCompiler for DoubledSet {
+        fn name(&self) -> &str {
+            self.0.name()
+        }
+
+        fn compile(&self, lex: &mut Lexer<Token>, variables: &[String], labels: &HashMap<String, u32>, routines: &HashMap<String, u32>, filename: &str, line: u32, debug: bool, current_routine: &Option<String>, num_instructions: usize) -> Vec<String> {
+            let compiled = self.0.compile(lex, variables, labels, routines, filename, line, debug, current_routine, num_instructions);
+            compiled.iter().chain(compiled.iter()).cloned().collect()
+        }
+
+        fn as_instruction(&self) -> Option<&Instruction> {
+            self.0.as_instruction()
+        }
+    }
+
+    #[test]
+    fn planted_address_mismatch_is_reported_and_minimized() {
+        let mut instructions = create_instructions(None, Target { version: 7, world: false }).unwrap();
+        let set = instructions.iter().position(|instruction| instruction.name() == "set").unwrap();
+        let set = instructions.remove(set);
+        instructions.insert(0, Box::new(DoubledSet(set)));
+
+        let program = Program::generate(&mut Rng(0x9e3779b97f4a7c15), 8);
+        assert!(check(&program, &instructions, "planted").is_some());
+        let (smaller, failure) = minimize(program.clone(), &instructions, "planted");
+        assert!(failure.contains("counted"), "{}", failure);
+        assert!(smaller.render().len() <= program.render().len());
+    }
+}
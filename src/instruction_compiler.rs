@@ -1,5 +1,5 @@
 use crate::token::Token;
-use crate::error;
+use crate::{error, warning};
 use crate::value::Value;
 
 use logos::Lexer;
@@ -11,31 +11,112 @@ use std::collections::HashMap;
 
 // used to make instructions and instruction groups work the same
 pub trait InstructionCompiler {
-    fn get_token(&self) -> Token;
+    fn name(&self) -> &str; // what the instruction's called in source, which is how it's found
+    #[allow(clippy::too_many_arguments)]
     fn compile(&self, lex: &mut Lexer<Token>, variables: &[String], labels: &HashMap<String, u32>, routines: &HashMap<String, u32>, filename: &str, line: u32, debug: bool, current_routine: &Option<String>, num_instructions: usize) -> Vec<String>; // like above, turns a value for a command from tokens back into text, double checking the arguments
+
+    // lets the decompiler look at the definitions of plain instructions and groups
+    fn as_instruction(&self) -> Option<&Instruction> {
+        None
+    }
+
+    fn as_group(&self) -> Option<&InstructionGroup> {
+        None
+    }
+
+    fn as_unavailable(&self) -> Option<&InstructionUnavailable> {
+        None
+    }
 }
 
 pub struct Instruction {
     pub name: String,
-    pub token: Token,
     pub arguments: Vec<Value>,
     pub super_instruction_name: Option<String>,
+    pub packed_colors: bool, // whether the target understands packed color constants (%rrggbbaa)
+    pub defaults: Vec<Option<String>>, // what each argument is when it's left off the end, if it can be
+}
+
+impl Instruction {
+    // how many arguments from index on are red, green and blue (and maybe alpha), which a color literal fills in all at once
+    pub fn color_width(&self, index: usize) -> usize {
+        let names: Vec<&str> = self.arguments[index..].iter().take(4).map(|arg| arg.name()).collect();
+        match names.as_slice() {
+            ["red", "green", "blue", "alpha", ..] => 4,
+            ["red", "green", "blue", ..] => 3,
+            _ => 0,
+        }
+    }
+
+    // whether a color literal can go in the argument at index, either split into channels or packed into one number
+    fn takes_color(&self, index: usize) -> bool {
+        self.color_width(index) > 0 || matches!(self.arguments[index], Value::Float(_) | Value::Any(_))
+    }
+
+    // turns a color literal into whatever the argument at index wants, and how many arguments that took care of
+    fn compile_color(&self, color: &[u8; 4], index: usize, slice: &str, filename: &str, line: u32) -> (Vec<String>, usize) {
+        let width = self.color_width(index);
+        if width > 0 {
+            if slice.len() == 9 && width < 4 {
+                error(&format!("this instruction has no alpha argument, so {} can't have one (use #rrggbb)", slice), filename, line);
+                println!("{} instruction is defined as: {}", "note:".bold(), self);
+                std::process::exit(1);
+            }
+            return (color.iter().take(width).map(|channel| channel.to_string()).collect(), width);
+        }
+        match &self.arguments[index] {
+            Value::Float(_) | Value::Any(_) if self.packed_colors => (vec![format!("%{}", slice[1..].to_lowercase())], 1),
+            Value::Float(_) | Value::Any(_) => {
+                error(&format!("{} would have to be packed into one number here, which this version of the game can't do (pick another with --target)", slice), filename, line);
+                std::process::exit(1);
+            },
+            arg => {
+                error(&format!("expected {} or variable, got {}", arg, slice), filename, line);
+                println!("{} instruction is defined as: {}", "note:".bold(), self);
+                std::process::exit(1);
+            },
+        }
+    }
 }
 
 impl InstructionCompiler for Instruction {
-    fn get_token(&self) -> Token {
-        self.token.clone()
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn as_instruction(&self) -> Option<&Instruction> {
+        Some(self)
     }
 
     fn compile(&self, lex: &mut Lexer<Token>, variables: &[String], _labels: &HashMap<String, u32>, _routines: &HashMap<String, u32>, filename: &str, line: u32, _debug: bool, _current_routine: &Option<String>, _num_instructions: usize) -> Vec<String> {
         let mut elements = vec![self.name.to_string()];
-        for arg in self.arguments.iter() {
-            if let Some(compiled) = arg.compile(lex, variables, false, filename, line) {
+        let mut index = 0;
+        while index < self.arguments.len() {
+            if let (Some(default), Some(Token::Newline) | None) = (&self.defaults[index], lex.clone().next()) {
+                elements.push(default.to_string());
+                index += 1;
+                continue;
+            }
+            if let Some(Token::Color(color)) = lex.clone().next() {
+                lex.next();
+                // the arguments left can all be left off and none of them is a color, so it was meant as a comment
+                if !self.takes_color(index) && self.defaults[index..].iter().all(|default| default.is_some()) {
+                    warning(&format!("{} can't go where {} is, so it's a comment rather than a color (put a space after the # to make that clear)", lex.slice(), self.arguments[index].name()), filename, line);
+                    lex.bump(lex.remainder().find('\n').unwrap_or(lex.remainder().len()));
+                    continue;
+                }
+                let (mut compiled, width) = self.compile_color(&color, index, lex.slice(), filename, line);
+                elements.append(&mut compiled);
+                index += width;
+                continue;
+            }
+            if let Some(compiled) = self.arguments[index].compile(lex, variables, false, filename, line) {
                 elements.push(compiled);
             } else {
                 println!("{} instruction is defined as: {}", "note:".bold(), self);
                 std::process::exit(1);
             }
+            index += 1;
         }
         vec![elements.join(" ")]
     }
@@ -49,8 +130,11 @@ impl Display for Instruction {
             write!(f, "{}", self.name)?;
         }
         //write!(f, );
-        for arg in self.arguments.iter() {
-            write!(f, " {}", arg.to_string_with_name())?;
+        for (arg, default) in self.arguments.iter().zip(self.defaults.iter()) {
+            match default {
+                Some(default) => write!(f, " [{} = {}]", arg.to_string_with_name(), default)?,
+                None => write!(f, " {}", arg.to_string_with_name())?,
+            }
         }
         Ok(())
     }
@@ -58,13 +142,17 @@ impl Display for Instruction {
 
 pub struct InstructionGroup { // for those Wacky Bois with sub instructions
     pub name: String,
-    pub token: Token,
-    pub sub_instructions: Vec<Instruction>
+    pub sub_instructions: Vec<Instruction>,
+    pub unavailable: Vec<InstructionUnavailable>, // sub-instructions the target doesn't have
 }
 
 impl InstructionCompiler for InstructionGroup {
-    fn get_token(&self) -> Token {
-        self.token.clone()
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn as_group(&self) -> Option<&InstructionGroup> {
+        Some(self)
     }
 
     fn compile(&self, lex: &mut Lexer<Token>, variables: &[String], labels: &HashMap<String, u32>, routines: &HashMap<String, u32>, filename: &str, line: u32, debug: bool, current_routine: &Option<String>, num_instructions: usize) -> Vec<String> {
@@ -72,17 +160,25 @@ impl InstructionCompiler for InstructionGroup {
         let next = lex.next();
         match next {
             Some(Token::Newline) => { error(&format!("unexpected newline after instruction \"{}\"", self.name), filename, line); std::process::exit(1); },
-            Some(token) => {
+            Some(_) => {
                 let mut has_inst = false;
                 for inst in self.sub_instructions.iter() {
-                    if inst.get_token() == token {
+                    if inst.name == lex.slice() {
                         elements.append(&mut inst.compile(lex, variables, labels, routines, filename, line, debug, current_routine, num_instructions));
                         has_inst = true;
                         break;
                     }
                 }
                 if !has_inst {
-                    error(&format!("unknown sub-instruction {} for \"{}\"", lex.slice(), self.name), filename, line);
+                    match self.unavailable.iter().find(|sub| sub.name == lex.slice()) {
+                        Some(sub) => error(&sub.message, filename, line),
+                        None => {
+                            // "PayTake" for "payTake" is an easy mistake to make
+                            let suggestion = self.sub_instructions.iter().find(|sub| sub.name.eq_ignore_ascii_case(lex.slice()))
+                                .map(|sub| format!(", did you mean {}?", sub.name)).unwrap_or_default();
+                            error(&format!("unknown sub-instruction {} for \"{}\"{}", lex.slice(), self.name, suggestion), filename, line)
+                        },
+                    }
                     std::process::exit(1);
                 }
             },
@@ -92,11 +188,32 @@ impl InstructionCompiler for InstructionGroup {
     }
 }
 
+// an instruction (or sub-instruction) that's in the instruction set, but not on the version of the game being targeted
+pub struct InstructionUnavailable {
+    pub name: String,
+    pub message: String,
+}
+
+impl InstructionCompiler for InstructionUnavailable {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn as_unavailable(&self) -> Option<&InstructionUnavailable> {
+        Some(self)
+    }
+
+    fn compile(&self, _lex: &mut Lexer<Token>, _variables: &[String], _labels: &HashMap<String, u32>, _routines: &HashMap<String, u32>, filename: &str, line: u32, _debug: bool, _current_routine: &Option<String>, _num_instructions: usize) -> Vec<String> {
+        error(&self.message, filename, line);
+        std::process::exit(1);
+    }
+}
+
 pub struct InstructionJump {}
 
 impl InstructionCompiler for InstructionJump {
-    fn get_token(&self) -> Token {
-        Token::InstJump
+    fn name(&self) -> &str {
+        "jump"
     }
 
     fn compile(&self, lex: &mut Lexer<Token>, variables: &[String], labels: &HashMap<String, u32>, _routines: &HashMap<String, u32>, filename: &str, line: u32, debug: bool, _current_routine: &Option<String>, _num_instructions: usize) -> Vec<String> {
@@ -106,7 +223,7 @@ impl InstructionCompiler for InstructionJump {
         }
 
         let mut elements = vec!["jump".to_string()];
-        let label_name = match lex.next() {
+        let label_name = match lex.next().map(|token| token.as_name(lex.slice())) {
             Some(Token::Name(label)) => {
                 if let Some(pos) = labels.get(&label) {
                     elements.push(format!("{}", pos));
@@ -156,13 +273,13 @@ impl InstructionCompiler for InstructionJump {
 pub struct InstructionGoto {}
 
 impl InstructionCompiler for InstructionGoto {
-    fn get_token(&self) -> Token {
-        Token::InstGoto
+    fn name(&self) -> &str {
+        "goto"
     }
 
     fn compile(&self, lex: &mut Lexer<Token>, _variables: &[String], labels: &HashMap<String, u32>, routines: &HashMap<String, u32>, filename: &str, line: u32, debug: bool, _current_routine: &Option<String>, _num_instructions: usize) -> Vec<String> {
         let mut elements = vec!["jump".to_string()];
-        match lex.next() {
+        match lex.next().map(|token| token.as_name(lex.slice())) {
             Some(Token::Name(label)) => {
                 if let Some(pos) = labels.get(&label) {
                     elements.push(format!("{} always", pos));
@@ -192,8 +309,8 @@ impl InstructionCompiler for InstructionGoto {
 pub struct InstructionOp {}
 
 impl InstructionCompiler for InstructionOp {
-    fn get_token(&self) -> Token {
-        Token::InstOp
+    fn name(&self) -> &str {
+        "op"
     }
 
     fn compile(&self, lex: &mut Lexer<Token>, variables: &[String], _labels: &HashMap<String, u32>, _routines: &HashMap<String, u32>, filename: &str, line: u32, _debug: bool, _current_routine: &Option<String>, _num_instructions: usize) -> Vec<String> {
@@ -236,12 +353,12 @@ impl InstructionCompiler for InstructionOp {
 pub struct InstructionGosub {}
 
 impl InstructionCompiler for InstructionGosub {
-    fn get_token(&self) -> Token {
-        Token::InstGosub
+    fn name(&self) -> &str {
+        "gosub"
     }
 
     fn compile(&self, lex: &mut Lexer<Token>, _variables: &[String], _labels: &HashMap<String, u32>, routines: &HashMap<String, u32>, filename: &str, line: u32, debug: bool, current_routine: &Option<String>, num_instructions: usize) -> Vec<String> {
-        let routine_name = match lex.next() {
+        let routine_name = match lex.next().map(|token| token.as_name(lex.slice())) {
             Some(Token::Name(label)) => label,
             _ => {
                 error(&format!("expected routine, got {}", lex.slice()), filename, line);
@@ -276,8 +393,8 @@ impl InstructionCompiler for InstructionGosub {
 pub struct InstructionGosubCond {}
 
 impl InstructionCompiler for InstructionGosubCond {
-    fn get_token(&self) -> Token {
-        Token::InstGosubCond
+    fn name(&self) -> &str {
+        "gosubc"
     }
 
     fn compile(&self, lex: &mut Lexer<Token>, variables: &[String], _labels: &HashMap<String, u32>, routines: &HashMap<String, u32>, filename: &str, line: u32, debug: bool, current_routine: &Option<String>, num_instructions: usize) -> Vec<String> {
@@ -286,7 +403,7 @@ impl InstructionCompiler for InstructionGosubCond {
             println!("or: gosubc label (label) always");
         }
         
-        let routine_name = match lex.next() {
+        let routine_name = match lex.next().map(|token| token.as_name(lex.slice())) {
             Some(Token::Name(label)) => label,
             _ => {
                 error(&format!("expected routine, got {}", lex.slice()), filename, line);
@@ -341,3 +458,37 @@ impl InstructionCompiler for InstructionGosubCond {
         vec![format!("set {}Return {}", routine_name, num_instructions + 2), elements.join(" ")]
     }
 }
+
+pub struct InstructionAssert {}
+
+impl InstructionCompiler for InstructionAssert {
+    fn name(&self) -> &str {
+        "assert"
+    }
+
+    // only the interpreter understands these, they're turned into "assert comp a b"
+    fn compile(&self, lex: &mut Lexer<Token>, variables: &[String], _labels: &HashMap<String, u32>, _routines: &HashMap<String, u32>, filename: &str, line: u32, _debug: bool, _current_routine: &Option<String>, _num_instructions: usize) -> Vec<String> {
+        fn print_def() {
+            println!("{} instruction is defined as: assert a (any) comp (Comp) b (any)", "note:".bold());
+        }
+
+        let a = match Value::Any("a".to_string()).compile(lex, variables, false, filename, line) {
+            Some(compiled) => compiled,
+            None => { print_def(); std::process::exit(1); },
+        };
+        let comp = match lex.next() {
+            Some(Token::Op(comp)) => comp,
+            _ => {
+                error(&format!("expected comparison, got {}", lex.slice()), filename, line);
+                print_def();
+                std::process::exit(1);
+            }
+        };
+        let b = match Value::Any("b".to_string()).compile(lex, variables, false, filename, line) {
+            Some(compiled) => compiled,
+            None => { print_def(); std::process::exit(1); },
+        };
+
+        vec![format!("assert {} {} {}", comp, a, b)]
+    }
+}
@@ -1,554 +1,224 @@
+use std::fs;
+
+use logos::Logos;
+use serde::Deserialize;
+
 use crate::instruction_compiler::*;
-use crate::value::Value;
 use crate::token::Token;
+use crate::value::Value;
+
+// the instruction set that comes with mlogp, see the top of the file for how it's laid out
+const BUNDLED_ISA: &str = include_str!("isa.toml");
+
+// instructions the compiler itself takes care of, which an instruction set can't redefine
+const BUILT_IN: [&str; 9] = ["jump", "goto", "gosub", "gosubc", "return", "endroutine", "op", "assert", "routine"];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstructionSet {
+    instruction: Vec<Definition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    name: String,
+    #[serde(default)]
+    arguments: Vec<String>,
+    #[serde(default)]
+    sub: Vec<Definition>,
+    since: Option<String>, // first version of the game that has it
+    until: Option<String>, // last version of the game that has it
+    #[serde(default)]
+    world: bool, // only for world processors
+}
+
+// "v7" -> 7
+pub fn parse_target(target: &str) -> Result<u32, String> {
+    match target.strip_prefix('v').and_then(|version| version.parse().ok()) {
+        Some(version) => Ok(version),
+        None => Err(format!("unknown game version \"{}\" (expected something like v7)", target)),
+    }
+}
+
+// what's being compiled for
+#[derive(Clone, Copy)]
+pub struct Target {
+    pub version: u32,
+    pub world: bool, // a world processor rather than a regular one
+}
+
+impl Definition {
+    // None if it's available on the target, otherwise why it isn't
+    fn unavailable(&self, full_name: &str, target: Target) -> Result<Option<String>, String> {
+        if self.world && !target.world {
+            return Ok(Some(format!("{} only works in world processors (compile with --world)", full_name)));
+        }
+        let target = target.version;
+        let since = self.since.as_deref().map(parse_target).transpose()?;
+        let until = self.until.as_deref().map(parse_target).transpose()?;
+        if since.map(|since| target >= since).unwrap_or(true) && until.map(|until| target <= until).unwrap_or(true) {
+            return Ok(None);
+        }
+        let versions = match (since, until) {
+            (Some(since), Some(until)) => format!("v{} to v{}", since, until),
+            (Some(since), None) => format!("v{} and later", since),
+            (None, Some(until)) => format!("v{} and earlier", until),
+            (None, None) => unreachable!(),
+        };
+        Ok(Some(format!("{} isn't available on v{}, only on {} (pick another with --target)", full_name, target, versions)))
+    }
+}
 
-// sets up a vector with all possible instructions
-pub fn create_instructions() -> Vec<Box<dyn InstructionCompiler>> {
-    vec![
-        // all the different kinds of instructions!
-        // write  value: float, cell: Building, address: int
-        Box::new(Instruction {
-            name: "write".to_string(),
-            token: Token::InstWrite,
-            arguments: vec![
-                Value::Float("value".to_string()),
-                Value::Name("cell".to_string(), "Building".to_string()),
-                Value::Int("address".to_string())
-            ],
-            super_instruction_name: None,
-        }),
-        // read  store: variable, cell: Building, address: int
-        Box::new(Instruction {
-            name: "read".to_string(),
-            token: Token::InstRead,
-            arguments: vec![
-                Value::Variable("store".to_string()),
-                Value::Name("cell".to_string(), "Building".to_string()),
-                Value::Int("address".to_string())
-            ],
-            super_instruction_name: None,
-        }),
-        // draw (group)
-        Box::new(InstructionGroup {
-            name: "draw".to_string(),
-            token: Token::InstDraw,
-            sub_instructions: vec![
-                // clear  r: int, g: int, b: int
-                Instruction {
-                    name: "clear".to_string(),
-                    token: Token::SubInstClear,
-                    arguments: vec![
-                        Value::Int("red".to_string()),
-                        Value::Int("green".to_string()),
-                        Value::Int("blue".to_string())
-                    ],
-                    super_instruction_name: Some("draw".to_string()),
-                },
-                // color  r: int, g: int, b: int
-                Instruction {
-                    name: "color".to_string(),
-                    token: Token::SubInstColor,
-                    arguments: vec![
-                        Value::Int("red".to_string()),
-                        Value::Int("green".to_string()),
-                        Value::Int("blue".to_string())
-                    ],
-                    super_instruction_name: Some("draw".to_string()),
-                },
-                // stroke  width: int
-                Instruction {
-                    name: "stroke".to_string(),
-                    token: Token::SubInstStroke,
-                    arguments: vec![
-                        Value::Int("width".to_string())
-                    ],
-                    super_instruction_name: Some("draw".to_string()),
-                },
-                // line  x1: int, y1: int, x2: int, y2: int
-                Instruction {
-                    name: "line".to_string(),
-                    token: Token::SubInstLine,
-                    arguments: vec![
-                        Value::Int("x1".to_string()),
-                        Value::Int("y1".to_string()),
-                        Value::Int("x2".to_string()),
-                        Value::Int("y2".to_string())
-                    ],
-                    super_instruction_name: Some("draw".to_string()),
-                },
-                // rect  x: int, y: int, w: int, h: int
-                Instruction {
-                    name: "rect".to_string(),
-                    token: Token::SubInstRect,
-                    arguments: vec![
-                        Value::Int("x".to_string()),
-                        Value::Int("y".to_string()),
-                        Value::Int("w".to_string()),
-                        Value::Int("h".to_string())
-                    ],
-                    super_instruction_name: Some("draw".to_string()),
-                },
-                // lineRect  x: int, y: int, w: int, h: int
-                Instruction {
-                    name: "lineRect".to_string(),
-                    token: Token::SubInstLineRect,
-                    arguments: vec![
-                        Value::Int("x".to_string()),
-                        Value::Int("y".to_string()),
-                        Value::Int("w".to_string()),
-                        Value::Int("h".to_string())
-                    ],
-                    super_instruction_name: Some("draw".to_string()),
-                },
-                // poly  x: int, y: int, sides: int, radius: float, rotation: float
-                Instruction {
-                    name: "poly".to_string(),
-                    token: Token::SubInstPoly,
-                    arguments: vec![
-                        Value::Int("x".to_string()),
-                        Value::Int("y".to_string()),
-                        Value::Int("sides".to_string()),
-                        Value::Float("radius".to_string()),
-                        Value::Float("rotation".to_string())
-                    ],
-                    super_instruction_name: Some("draw".to_string()),
-                },
-                // linePoly  x: int, y: int, sides: int, radius: float, rotation: float
-                Instruction {
-                    name: "linePoly".to_string(),
-                    token: Token::SubInstLinePoly,
-                    arguments: vec![
-                        Value::Int("x".to_string()),
-                        Value::Int("y".to_string()),
-                        Value::Int("sides".to_string()),
-                        Value::Float("radius".to_string()),
-                        Value::Float("rotation".to_string())
-                    ],
-                    super_instruction_name: Some("draw".to_string()),
-                },
-                // triangle  x1: int, y1: int, x2: int, y2: int, x3: int, y3: int
-                Instruction {
-                    name: "triangle".to_string(),
-                    token: Token::SubInstTriangle,
-                    arguments: vec![
-                        Value::Int("x1".to_string()),
-                        Value::Int("y1".to_string()),
-                        Value::Int("x2".to_string()),
-                        Value::Int("y2".to_string()),
-                        Value::Int("x3".to_string()),
-                        Value::Int("y3".to_string())
-                    ],
-                    super_instruction_name: Some("draw".to_string()),
-                },
-                // image  x: int, y: int, image: UnlockableContent, size: float, rotation: float
-                Instruction {
-                    name: "image".to_string(),
-                    token: Token::SubInstImage,
-                    arguments: vec![
-                        Value::Int("x".to_string()),
-                        Value::Int("y".to_string()),
-                        Value::Name("image".to_string(), "UnlockableContent".to_string()),
-                        Value::Float("size".to_string()),
-                        Value::Float("rotation".to_string())
-                    ],
-                    super_instruction_name: Some("draw".to_string()),
+// turns "name: kind" into a value, and "name: kind = default" into one that can be left off
+fn parse_argument(argument: &str, instruction: &str) -> Result<(Value, Option<String>), String> {
+    let (argument, default) = match argument.split_once('=') {
+        Some((argument, default)) if !default.trim().is_empty() => (argument, Some(default.trim().to_string())),
+        Some(_) => return Err(format!("argument \"{}\" of {} is missing its default after the =", argument, instruction)),
+        None => (argument, None),
+    };
+    let (name, kind) = match argument.split_once(':') {
+        Some((name, kind)) if !name.trim().is_empty() && !kind.trim().is_empty() => (name.trim().to_string(), kind.trim()),
+        _ => return Err(format!("argument \"{}\" of {} should look like \"name: kind\"", argument, instruction)),
+    };
+    let value = match kind {
+        "bool" => Value::Bool(name),
+        "float" => Value::Float(name),
+        "int" => Value::Int(name),
+        "string" => Value::String(name),
+        "any" => Value::Any(name),
+        "output" => Value::Variable(name),
+        category => Value::Name(name, category.to_string()),
+    };
+    Ok((value, default))
+}
+
+// an instruction has to be something the lexer reads as a name, otherwise there'd be no way to write it
+fn check_name(definition: &Definition, super_instruction_name: Option<&str>) -> Result<(), String> {
+    let mut lex = Token::lexer(&definition.name);
+    let is_name = matches!(lex.next().map(|token| token.as_name(lex.slice())), Some(Token::Name(_))) && lex.next().is_none();
+    if !is_name {
+        return Err(format!("\"{}\" can't be used as an instruction name", full_name(definition, super_instruction_name)));
+    }
+    Ok(())
+}
+
+fn full_name(definition: &Definition, super_instruction_name: Option<&str>) -> String {
+    match super_instruction_name {
+        Some(group) => format!("{} {}", group, definition.name),
+        None => definition.name.to_string(),
+    }
+}
+
+fn parse_instruction(definition: &Definition, super_instruction_name: Option<&str>, packed_colors: bool) -> Result<Instruction, String> {
+    let full_name = full_name(definition, super_instruction_name);
+    if !definition.sub.is_empty() {
+        return Err(format!("{} can't have sub-instructions of its own", full_name));
+    }
+    let (arguments, defaults): (Vec<Value>, Vec<Option<String>>) = definition.arguments.iter()
+        .map(|argument| parse_argument(argument, &full_name))
+        .collect::<Result<Vec<_>, String>>()?
+        .into_iter()
+        .unzip();
+    // only the last arguments can be left off, otherwise there'd be no telling which ones were
+    if let Some(first) = defaults.iter().position(|default| default.is_some()) {
+        if defaults[first..].iter().any(|default| default.is_none()) {
+            return Err(format!("{} has an argument without a default after one with a default", full_name));
+        }
+    }
+    Ok(Instruction {
+        name: definition.name.to_string(),
+        arguments,
+        super_instruction_name: super_instruction_name.map(|name| name.to_string()),
+        packed_colors,
+        defaults,
+    })
+}
+
+// splits definitions into the ones available on the target (only one per name) and
+// placeholders for the rest, so using them gives a clear error
+fn filter_target<'a>(definitions: &'a [Definition], super_instruction_name: Option<&str>, target: Target) -> Result<(Vec<&'a Definition>, Vec<InstructionUnavailable>), String> {
+    let mut available: Vec<&Definition> = Vec::new();
+    let mut unavailable: Vec<InstructionUnavailable> = Vec::new();
+    for definition in definitions.iter() {
+        check_name(definition, super_instruction_name)?;
+        let full_name = full_name(definition, super_instruction_name);
+        if super_instruction_name.is_none() && BUILT_IN.contains(&definition.name.as_str()) {
+            return Err(format!("{} is built into the compiler and can't be redefined", definition.name));
+        }
+        match definition.unavailable(&full_name, target)? {
+            None => {
+                if available.iter().any(|other| other.name == definition.name) {
+                    return Err(format!("{} is defined more than once for v{}", full_name, target.version));
                 }
-            ]
-        }),
-        // drawflush  display: Building
-        Box::new(Instruction {
-            name: "drawflush".to_string(),
-            token: Token::InstDrawFlush,
-            arguments: vec![
-                Value::Name("display".to_string(), "Building".to_string())
-            ],
-            super_instruction_name: None,
-        }),
-        // print  text: string
-        Box::new(Instruction {
-            name: "print".to_string(),
-            token: Token::InstPrint,
-            arguments: vec![
-                Value::String("text".to_string())
-            ],
-            super_instruction_name: None,
-        }),
-        // printflush  msgblock: Building
-        Box::new(Instruction {
-            name: "printflush".to_string(),
-            token: Token::InstPrintFlush,
-            arguments: vec![
-                Value::Name("msgblock".to_string(), "Building".to_string())
-            ],
-            super_instruction_name: None,
-        }),
-        // getlink  store: variable, index: int
-        Box::new(Instruction {
-            name: "getlink".to_string(),
-            token: Token::InstGetLink,
-            arguments: vec![
-                Value::Variable("store".to_string()),
-                Value::Int("index".to_string())
-            ],
-            super_instruction_name: None,
-        }),
-        // control (group)
-        Box::new(InstructionGroup {
-            name: "control".to_string(),
-            token: Token::InstControl,
-            sub_instructions: vec![
-                // enabled  target: Building, enabled: bool
-                Instruction {
-                    name: "enabled".to_string(),
-                    token: Token::SubInstEnabled,
-                    arguments: vec![
-                        Value::Name("target".to_string(), "Building".to_string()),
-                        Value::Bool("enabled".to_string())
-                    ],
-                    super_instruction_name: Some("control".to_string()),
-                },
-                // shoot  turret: Building, x: float, y: float, shoot: bool
-                Instruction {
-                    name: "shoot".to_string(),
-                    token: Token::SubInstShoot,
-                    arguments: vec![
-                        Value::Name("turret".to_string(), "Building".to_string()),
-                        Value::Float("x".to_string()), Value::Float("y".to_string()),
-                        Value::Bool("shoot".to_string())
-                    ],
-                    super_instruction_name: Some("control".to_string()),
-                },
-                // shootp  turret: Building, target: Healthc, shoot: bool
-                Instruction {
-                    name: "shootp".to_string(),
-                    token: Token::SubInstShootP,
-                    arguments: vec![
-                        Value::Name("turret".to_string(), "Building".to_string()),
-                        Value::Name("target".to_string(), "Healthc".to_string()),
-                        Value::Bool("shoot".to_string())
-                    ],
-                    super_instruction_name: Some("control".to_string()),
-                },
-                // configure  build: Building, config: Content
-                Instruction {
-                    name: "configure".to_string(),
-                    token: Token::SubInstConfigure,
-                    arguments: vec![
-                        Value::Name("build".to_string(), "Building".to_string()),
-                        Value::Name("config".to_string(), "Content".to_string())
-                    ],
-                    super_instruction_name: Some("control".to_string()),
-                },
-                // color  illuminator: Building, r: float, g: float, b: float
-                Instruction {
-                    name: "color".to_string(),
-                    token: Token::SubInstColor,
-                    arguments: vec![
-                        Value::Name("illuminator".to_string(), "Building".to_string()),
-                        Value::Int("red".to_string()),
-                        Value::Int("green".to_string()),
-                        Value::Int("blue".to_string())
-                    ],
-                    super_instruction_name: Some("control".to_string()),
-                },
-            ]
-        }),
-        // radar  turret: Ranged, prop1: TargetType, prop2: TargetType, prop3: TargetType, order: bool, output: variable
-        Box::new(Instruction {
-            name: "radar".to_string(),
-            token: Token::InstRadar,
-            arguments: vec![
-                Value::Name("turret".to_string(), "Ranged".to_string()),
-                Value::Name("prop1".to_string(), "TargetType".to_string()),
-                Value::Name("prop2".to_string(), "TargetType".to_string()),
-                Value::Name("prop3".to_string(), "TargetType".to_string()),
-                Value::Bool("order".to_string()),
-                Value::Variable("output".to_string())
-            ],
-            super_instruction_name: None,
-        }),
-        // sensor  store: variable, block: Building, sense: Sensable
-        Box::new(Instruction {
-            name: "sensor".to_string(),
-            token: Token::InstSensor,
-            arguments: vec![
-                Value::Variable("store".to_string()),
-                Value::Name("block".to_string(), "Building".to_string()),
-                Value::Name("sense".to_string(), "Sensable".to_string())
-            ],
-            super_instruction_name: None,
-        }),
-        // set  varname: variable, value: any
-        Box::new(Instruction {
-            name: "set".to_string(),
-            token: Token::InstSet,
-            arguments: vec![
-                Value::Variable("varname".to_string()),
-                Value::Any("value".to_string())
-            ],
-            super_instruction_name: None,
-        }),
-        // end
-        Box::new(Instruction {
-            name: "end".to_string(),
-            token: Token::InstEnd,
-            arguments: vec![],
-            super_instruction_name: None,
-        }),
-        // ucontrol (group)
-        Box::new(InstructionGroup {
-            name: "ucontrol".to_string(),
-            token: Token::InstUnitControl,
-            sub_instructions: vec![
-                // idle
-                Instruction {
-                    name: "idle".to_string(),
-                    token: Token::SubInstIdle,
-                    arguments: vec![],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // stop
-                Instruction {
-                    name: "stop".to_string(),
-                    token: Token::SubInstStop,
-                    arguments: vec![],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // move  x: float, y: float
-                Instruction {
-                    name: "move".to_string(),
-                    token: Token::SubInstMove,
-                    arguments: vec![
-                        Value::Float("x".to_string()),
-                        Value::Float("y".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // approach  x: float, y: float, radius: float
-                Instruction {
-                    name: "approach".to_string(),
-                    token: Token::SubInstApproach,
-                    arguments: vec![
-                        Value::Float("x".to_string()),
-                        Value::Float("y".to_string()),
-                        Value::Float("radius".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // boost  enable: bool
-                Instruction {
-                    name: "boost".to_string(),
-                    token: Token::SubInstBoost,
-                    arguments: vec![
-                        Value::Bool("enable".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // pathfind
-                Instruction {
-                    name: "pathfind".to_string(),
-                    token: Token::SubInstPathFind,
-                    arguments: vec![],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // target  x: float, y: float, shoot: bool
-                Instruction {
-                    name: "target".to_string(),
-                    token: Token::SubInstTarget,
-                    arguments: vec![
-                        Value::Float("x".to_string()),
-                        Value::Float("y".to_string()),
-                        Value::Bool("shoot".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // targetp  target: Healthc, shoot: bool
-                Instruction {
-                    name: "targetp".to_string(),
-                    token: Token::SubInstTargetP,
-                    arguments: vec![
-                        Value::Name("target".to_string(), "Healthc".to_string()),
-                        Value::Bool("shoot".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // itemDrop  to: Building, amount: int
-                Instruction {
-                    name: "itemDrop".to_string(),
-                    token: Token::SubInstItemDrop,
-                    arguments: vec![
-                        Value::Name("to".to_string(), "Building".to_string()),
-                        Value::Int("amount".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // itemTake  from: Building, item: Item, amount: int
-                Instruction {
-                    name: "itemTake".to_string(),
-                    token: Token::SubInstItemTake,
-                    arguments: vec![
-                        Value::Name("from".to_string(), "Building".to_string()),
-                        Value::Name("item".to_string(), "Item".to_string()),
-                        Value::Int("amount".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // payDrop
-                Instruction {
-                    name: "payDrop".to_string(),
-                    token: Token::SubInstPayDrop,
-                    arguments: vec![],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // payTake  takeUnits: bool
-                Instruction {
-                    name: "payTake".to_string(),
-                    token: Token::SubInstPayTake,
-                    arguments: vec![
-                        Value::Bool("takeUnits".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // mine  x: f32, y: f32
-                Instruction {
-                    name: "mine".to_string(),
-                    token: Token::SubInstMine,
-                    arguments: vec![
-                        Value::Float("x".to_string()),
-                        Value::Float("y".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // flag  flag: float
-                Instruction {
-                    name: "flag".to_string(),
-                    token: Token::SubInstFlag,
-                    arguments: vec![
-                        Value::Float("flag".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // build  x: float, y: float, block: Block, rotation: int, config: variable
-                Instruction {
-                    name: "build".to_string(),
-                    token: Token::SubInstBuild,
-                    arguments: vec![
-                        Value::Float("x".to_string()),
-                        Value::Float("y".to_string()),
-                        Value::Name("block".to_string(), "Block".to_string()),
-                        Value::Int("rotation".to_string()),
-                        Value::Variable("config".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // getBlock  x: float, y: float, type: variable (Block), building: variable (Building)
-                Instruction {
-                    name: "getBlock".to_string(),
-                    token: Token::SubInstGetBlock,
-                    arguments: vec![
-                        Value::Float("x".to_string()),
-                        Value::Float("y".to_string()),
-                        Value::Variable("block".to_string()),
-                        Value::Variable("building".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-                // within  x: float, y: float, radius: float, result: bool
-                Instruction {
-                    name: "within".to_string(),
-                    token: Token::SubInstWithin,
-                    arguments: vec![
-                        Value::Float("x".to_string()),
-                        Value::Float("y".to_string()),
-                        Value::Float("radius".to_string()),
-                        Value::Variable("result".to_string())
-                    ],
-                    super_instruction_name: Some("ucontrol".to_string()),
-                },
-            ]
-        }),
-        // uradar  a: Target, b: Target, c: Target, sort: SortType, 0: bool, order: bool, result: variable (Healthc)
-        Box::new(Instruction {
-            name: "uradar".to_string(),
-            token: Token::InstUnitRadar,
-            arguments: vec![
-                Value::Name("a".to_string(), "Target".to_string()),
-                Value::Name("b".to_string(), "Target".to_string()),
-                Value::Name("c".to_string(), "Target".to_string()),
-                Value::Name("sort".to_string(), "SortType".to_string()),
-                Value::Bool("0".to_string()),
-                Value::Bool("order".to_string()),
-                Value::Variable("result".to_string())
-            ],
-            super_instruction_name: None,
-        }),
-        // ulocate (group)
-        Box::new(InstructionGroup {
-            name: "ulocate".to_string(),
-            token: Token::InstUnitLocate,
-            sub_instructions: vec![
-                // ore  ore: Item, outX: variable (float), outY: variable (float), outFound: variable (bool)
-                Instruction {
-                    name: "ore".to_string(),
-                    token: Token::SubInstOre,
-                    arguments: vec![
-                        Value::Name("ore".to_string(), "Item".to_string()),
-                        Value::Variable("outX".to_string()),
-                        Value::Variable("outY".to_string()),
-                        Value::Variable("outFound".to_string())
-                    ],
-                    super_instruction_name: Some("ulocate".to_string()),
-                },
-                // building  type: BlockFlag, enemy: bool, outX: variable (float), outY: variable (float), outFound: variable (bool)
-                Instruction {
-                    name: "building".to_string(),
-                    token: Token::SubInstBuilding,
-                    arguments: vec![
-                        Value::Name("type".to_string(), "BlockFlag".to_string()),
-                        Value::Bool("enemy".to_string()),
-                        Value::Variable("outX".to_string()),
-                        Value::Variable("outY".to_string()),
-                        Value::Variable("outFound".to_string()),
-                        Value::Variable("outBuilding".to_string())
-                    ],
-                    super_instruction_name: Some("ulocate".to_string()),
-                },
-                // spawn  outX: variable (float), outY: variable (float), outFound: variable (bool)
-                Instruction {
-                    name: "spawn".to_string(),
-                    token: Token::SubInstSpawn,
-                    arguments: vec![
-                        Value::Variable("outX".to_string()),
-                        Value::Variable("outY".to_string()),
-                        Value::Variable("outFound".to_string())
-                    ],
-                    super_instruction_name: Some("ulocate".to_string()),
-                },
-            ]
-        }),
-        // noop
-        Box::new(Instruction {
-            name: "noop".to_string(),
-            token: Token::InstNoOp,
-            arguments: vec![],
-            super_instruction_name: None,
-        }),
+                available.push(definition);
+            },
+            Some(message) => {
+                if !unavailable.iter().any(|other| other.name == definition.name) {
+                    unavailable.push(InstructionUnavailable { name: definition.name.to_string(), message });
+                }
+            },
+        }
+    }
+    unavailable.retain(|placeholder| !available.iter().any(|definition| definition.name == placeholder.name));
+    Ok((available, unavailable))
+}
+
+// sets up a vector with all possible instructions on a version of the game, from the bundled
+// instruction set or one given with --isa
+pub fn create_instructions(isa: Option<&str>, target: Target) -> Result<Vec<Box<dyn InstructionCompiler>>, String> {
+    let string = match isa {
+        Some(filename) => fs::read_to_string(filename).map_err(|err| format!("couldn't read file: {}", err))?,
+        None => BUNDLED_ISA.to_string(),
+    };
+    let set: InstructionSet = if isa.map(|filename| filename.ends_with(".json")).unwrap_or(false) {
+        serde_json::from_str(&string).map_err(|err| format!("invalid instruction set: {}", err))?
+    } else {
+        toml::from_str(&string).map_err(|err| format!("invalid instruction set: {}", err))?
+    };
+
+    let mut instructions: Vec<Box<dyn InstructionCompiler>> = Vec::new();
+    let (available, unavailable) = filter_target(&set.instruction, None, target)?;
+    // %rrggbbaa came along with packcolor
+    let packed_colors = available.iter().any(|definition| definition.name == "packcolor");
+    for definition in available {
+        if definition.sub.is_empty() {
+            instructions.push(Box::new(parse_instruction(definition, None, packed_colors)?));
+        } else {
+            // for those Wacky Bois with sub instructions
+            if !definition.arguments.is_empty() {
+                return Err(format!("{} has sub-instructions, so its arguments go on those instead", definition.name));
+            }
+            let (subs, unavailable) = filter_target(&definition.sub, Some(&definition.name), target)?;
+            let sub_instructions = subs.into_iter()
+                .map(|sub| parse_instruction(sub, Some(&definition.name), packed_colors))
+                .collect::<Result<Vec<Instruction>, String>>()?;
+            instructions.push(Box::new(InstructionGroup { name: definition.name.to_string(), sub_instructions, unavailable }));
+        }
+    }
+    for placeholder in unavailable {
+        instructions.push(Box::new(placeholder));
+    }
+
+    // jump  addr: label, comp: Comp, a: any, <b: any>
+    // jump  addr: label, always
+    instructions.push(Box::new(InstructionJump {}));
+
+    // goto  addr: label
+    instructions.push(Box::new(InstructionGoto {}));
+
+    // gosub  addr: routine
+    instructions.push(Box::new(InstructionGosub {}));
 
-        // jump  addr: label, comp: Comp, a: any, <b: any>
-        // jump  addr: label, always
-        Box::new(InstructionJump {}),
+    // gosubcond  addr: routine, comp: Comp, a: any, <b: any>
+    // gosubcond  addr: routine, always
+    instructions.push(Box::new(InstructionGosubCond {}));
 
-        // goto  addr: label
-        Box::new(InstructionGoto {}),
+    // op  op: op, result: variable, a: any, <b: any>
+    instructions.push(Box::new(InstructionOp {}));
 
-        // gosub  addr: routine
-        Box::new(InstructionGosub {}),
-        
-        // gosubcond  addr: routine, comp: Comp, a: any, <b: any>
-        // gosubcond  addr: routine, always
-        Box::new(InstructionGosubCond {}),
+    // assert  a: any, comp: Comp, b: any (tests only)
+    instructions.push(Box::new(InstructionAssert {}));
 
-        // op  op: op, result: variable, a: any, <b: any>
-        Box::new(InstructionOp {}),
-    ]
+    Ok(instructions)
 }
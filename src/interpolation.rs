@@ -0,0 +1,131 @@
+use logos::{Lexer, Logos};
+
+use crate::token::Token;
+use crate::misc::error;
+
+// print "hp: {hp}/{max}" turns into print "hp: ", print hp, print "/", print max
+// or on versions with format, print "hp: {0}/{1}", format hp, format max (when that's shorter)
+
+enum Piece {
+    Text(String),
+    Name(String),
+}
+
+// variables, and @ names like @unit
+fn is_name(name: &str) -> bool {
+    let (name, allowed) = match name.strip_prefix('@') {
+        Some(name) => (name, "_-"),
+        None => (name, "_"),
+    };
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || allowed.contains(c))
+}
+
+// splits a string literal (quotes and all) into text and the names in braces, None if there aren't any names.
+// braces around anything else (like {0}) are left alone
+fn split(literal: &str) -> Option<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut rest = &literal[1..literal.len() - 1];
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) if is_name(&after[..end]) => {
+                text.push_str(&rest[..start]);
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(Piece::Name(after[..end].to_string()));
+                rest = &after[end + 1..];
+            },
+            _ => {
+                text.push_str(&rest[..=start]);
+                rest = after;
+            },
+        }
+    }
+    text.push_str(rest);
+    if !pieces.iter().any(|piece| matches!(piece, Piece::Name(_))) {
+        return None;
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Some(pieces)
+}
+
+// format fills in {0} to {9}, so it only works for up to 10 names
+fn use_format(pieces: &[Piece], format: bool) -> bool {
+    let names = pieces.iter().filter(|piece| matches!(piece, Piece::Name(_))).count();
+    format && names <= 10 && names + 1 < pieces.len()
+}
+
+// format fills in the lowest placeholder anywhere in what's been printed so far, not just the string before it.
+// there's no telling what else got printed before a flush, so it's only safe when no string in the whole
+// program has braces of its own (the names that get filled in don't count)
+pub fn format_is_safe(string: &str) -> bool {
+    for token in Token::lexer(string) {
+        if let Token::String(literal) = token {
+            let has_braces = match split(&literal) {
+                Some(pieces) => pieces.iter().any(|piece| matches!(piece, Piece::Text(text) if text.contains('{'))),
+                None => literal.contains('{'),
+            };
+            if has_braces {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn expand(pieces: &[Piece], format: bool) -> Vec<String> {
+    if !use_format(pieces, format) {
+        return pieces.iter().map(|piece| match piece {
+            Piece::Text(text) => format!("print \"{}\"", text),
+            Piece::Name(name) => format!("print {}", name),
+        }).collect();
+    }
+    let mut text = String::new();
+    let mut formats = Vec::new();
+    for piece in pieces.iter() {
+        match piece {
+            Piece::Text(piece) => text.push_str(piece),
+            Piece::Name(name) => {
+                text.push_str(&format!("{{{}}}", formats.len()));
+                formats.push(format!("format {}", name));
+            },
+        }
+    }
+    std::iter::once(format!("print \"{}\"", text)).chain(formats).collect()
+}
+
+// the string after a print, if it has names in it
+fn peek(lex: &Lexer<Token>, instruction: &str) -> Option<Vec<Piece>> {
+    if instruction != "print" {
+        return None;
+    }
+    match lex.clone().next() {
+        Some(Token::String(literal)) => split(&literal),
+        _ => None,
+    }
+}
+
+// how many instructions an instruction turns into if it's a print with names in it
+pub fn count(lex: &Lexer<Token>, instruction: &str, format: bool) -> Option<usize> {
+    peek(lex, instruction).map(|pieces| expand(&pieces, format).len())
+}
+
+// compiles a print with names in it, or leaves the lexer alone and gives None if it's anything else
+pub fn compile(lex: &mut Lexer<Token>, instruction: &str, variables: &[String], format: bool, filename: &str, line: u32) -> Option<Vec<String>> {
+    let pieces = peek(lex, instruction)?;
+    lex.next();
+    for piece in pieces.iter() {
+        if let Piece::Name(name) = piece {
+            if !name.starts_with('@') && !variables.contains(name) {
+                error(&format!("{} isn't a variable, so it can't go in a string (maybe you forgot to create it?)", name), filename, line);
+                std::process::exit(1);
+            }
+        }
+    }
+    Some(expand(&pieces, format))
+}
@@ -0,0 +1,678 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Formatter;
+use std::fmt::Display;
+
+use crate::misc::split_line;
+use crate::memory::memory_size;
+use crate::display::{Display as LogicDisplay, DrawOutput, MAX_DRAW_BUFFER};
+use crate::scenario::Scenario;
+use crate::content::database;
+
+// the game stops adding to the text buffer past this point
+const MAX_TEXT_BUFFER: usize = 400;
+
+// anything a variable can hold
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    Null,
+    Number(f64),
+    String(String),
+    Building(String),
+    Unit(String),
+    Content(String),
+}
+
+impl Object {
+    // numeric value of an object, the same way the game works it out
+    pub fn num(&self) -> f64 {
+        match self {
+            Object::Null => 0.0,
+            Object::Number(num) => *num,
+            _ => 1.0,
+        }
+    }
+
+    fn is_object(&self) -> bool {
+        !matches!(self, Object::Number(_))
+    }
+}
+
+impl Display for Object {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Object::Null => write!(f, "null"),
+            Object::Number(num) => {
+                if (num - num.round()).abs() < 0.00001 {
+                    write!(f, "{}", *num as i64)
+                } else {
+                    write!(f, "{}", num)
+                }
+            },
+            Object::String(string) => write!(f, "{}", string.replace("\\n", "\n")),
+            Object::Building(name) => write!(f, "{}", name),
+            Object::Unit(name) => write!(f, "{}", name),
+            Object::Content(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Running,
+    Ended, // hit an end instruction or ran off the end of the program, the game would start over from the top
+    Stopped, // hit a stop instruction, the game would sit there forever
+    AssertionFailed(String),
+}
+
+// a virtual logic processor, runs compiled mlog
+pub struct Processor {
+    pub program: Vec<Vec<String>>,
+    pub counter: usize,
+    pub variables: HashMap<String, Object>,
+    pub text_buffer: String,
+    pub messages: BTreeMap<String, String>,
+    pub memory: HashMap<String, Vec<f64>>,
+    pub draw_buffer: Vec<(String, Vec<f64>)>,
+    pub displays: BTreeMap<String, LogicDisplay>,
+    pub display_size: usize,
+    pub draw_output: Option<DrawOutput>,
+    pub executed: u64,
+    pub waited: u64, // ticks spent in wait instructions
+    pub counts: Vec<u64>, // how many times each instruction has run
+    pub instructions_per_tick: u64,
+    pub scenario: Scenario,
+    pub bound_unit: Object,
+    pub flags: HashSet<String>, // global flags set by world processors
+    calls: HashMap<String, usize>, // how many times each kind of canned scenario result has been handed out
+    rng: u64,
+    permutation: Vec<usize>,
+}
+
+impl Processor {
+    pub fn new(code: &[String]) -> Processor {
+        let program: Vec<Vec<String>> = code.iter().map(|line| split_line(line)).filter(|words| !words.is_empty()).collect();
+        let mut processor = Processor {
+            counts: vec![0; program.len()],
+            instructions_per_tick: 8, // same as a logic processor
+            scenario: Scenario::default(),
+            bound_unit: Object::Null,
+            flags: HashSet::new(),
+            calls: HashMap::new(),
+            program,
+            counter: 0,
+            variables: HashMap::new(),
+            text_buffer: String::new(),
+            messages: BTreeMap::new(),
+            memory: HashMap::new(),
+            draw_buffer: Vec::new(),
+            displays: BTreeMap::new(),
+            display_size: 80,
+            draw_output: None,
+            executed: 0,
+            waited: 0,
+            rng: 0x2545f4914f6cdd1d,
+            permutation: (0..256).collect(),
+        };
+
+        // shuffle the permutation table for noise, it's seeded so runs are always the same
+        for i in (1..256).rev() {
+            let j = (processor.random() * (i + 1) as f64) as usize;
+            processor.permutation.swap(i, j);
+        }
+        let copy = processor.permutation.clone();
+        processor.permutation.extend(copy);
+
+        processor
+    }
+
+    // xorshift, nothing fancy
+    fn random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // 2d simplex noise, like the noise op in game
+    fn noise(&self, x: f64, y: f64) -> f64 {
+        const GRADIENTS: [(f64, f64); 8] = [(1.0, 1.0), (-1.0, 1.0), (1.0, -1.0), (-1.0, -1.0), (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)];
+        let f2 = 0.5 * (3f64.sqrt() - 1.0);
+        let g2 = (3.0 - 3f64.sqrt()) / 6.0;
+
+        let s = (x + y) * f2;
+        let i = (x + s).floor();
+        let j = (y + s).floor();
+        let t = (i + j) * g2;
+        let x0 = x - (i - t);
+        let y0 = y - (j - t);
+        let (i1, j1) = if x0 > y0 { (1.0, 0.0) } else { (0.0, 1.0) };
+        let corners = [
+            (x0, y0, 0.0, 0.0),
+            (x0 - i1 + g2, y0 - j1 + g2, i1, j1),
+            (x0 - 1.0 + 2.0 * g2, y0 - 1.0 + 2.0 * g2, 1.0, 1.0),
+        ];
+
+        let mut total = 0.0;
+        for (cx, cy, di, dj) in corners {
+            let falloff = 0.5 - cx * cx - cy * cy;
+            if falloff > 0.0 {
+                let ii = (i + di).rem_euclid(256.0) as usize;
+                let jj = (j + dj).rem_euclid(256.0) as usize;
+                let (gx, gy) = GRADIENTS[self.permutation[ii + self.permutation[jj]] % 8];
+                total += falloff.powi(4) * (gx * cx + gy * cy);
+            }
+        }
+        70.0 * total
+    }
+
+    // looks up the value of an argument, be it a literal, variable or constant
+    pub fn get(&self, word: &str) -> Object {
+        if word.starts_with('"') {
+            return Object::String(word.trim_matches('"').to_string());
+        }
+        match word {
+            "true" => return Object::Number(1.0),
+            "false" => return Object::Number(0.0),
+            "null" => return Object::Null,
+            _ => (),
+        }
+        if word.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') {
+            if let Ok(num) = word.parse::<f64>() {
+                return Object::Number(num);
+            }
+        }
+        if let Some(hex) = word.strip_prefix('%') {
+            // packed color, the same bits packcolor makes
+            let hex = if hex.len() == 6 { format!("{}ff", hex) } else { hex.to_string() };
+            if let Ok(packed) = u64::from_str_radix(&hex, 16) {
+                return Object::Number(f64::from_bits(packed));
+            }
+        }
+        if let Some(name) = word.strip_prefix('@') {
+            let tick = self.tick() as f64;
+            return match name {
+                "counter" => Object::Number(self.counter as f64),
+                "ipt" => Object::Number(self.instructions_per_tick as f64),
+                "tick" => Object::Number(tick),
+                "time" => Object::Number(tick * 1000.0 / 60.0),
+                "second" => Object::Number(tick / 60.0),
+                "links" => Object::Number(self.scenario.links.len() as f64),
+                "pi" => Object::Number(std::f64::consts::PI),
+                "e" => Object::Number(std::f64::consts::E),
+                "degToRad" => Object::Number(std::f64::consts::PI / 180.0),
+                "radToDeg" => Object::Number(180.0 / std::f64::consts::PI),
+                "this" => Object::Building("processor1".to_string()),
+                "unit" => self.bound_unit.clone(),
+                _ => Object::Content(name.to_string()),
+            };
+        }
+        if let Some(object) = self.variables.get(word) {
+            return object.clone();
+        }
+        if self.scenario.is_link(word) {
+            return Object::Building(word.to_string());
+        }
+
+        // unset names that look like cell1, message2 etc. are linked buildings
+        let trimmed = word.trim_end_matches(|c: char| c.is_ascii_digit());
+        if trimmed.len() < word.len() && !trimmed.is_empty() && trimmed.chars().all(|c| c.is_ascii_lowercase()) {
+            Object::Building(word.to_string())
+        } else {
+            Object::Null
+        }
+    }
+
+    // stores a value into a variable, quietly ignoring constants like the game does
+    pub fn set(&mut self, word: &str, object: Object) {
+        let object = match object {
+            Object::Number(num) if !num.is_finite() => Object::Null,
+            object => object,
+        };
+        if word == "@counter" {
+            let target = object.num();
+            self.counter = if target < 0.0 { self.program.len() } else { target as usize };
+        } else if !word.starts_with(|c: char| c.is_ascii_digit() || "-.\"@".contains(c)) && !matches!(word, "true" | "false" | "null") {
+            self.variables.insert(word.to_string(), object);
+        }
+    }
+
+    fn op(&mut self, op: &str, a: &Object, b: &Object) -> Result<Object, String> {
+        fn bool_num(value: bool) -> Object {
+            Object::Number(if value { 1.0 } else { 0.0 })
+        }
+
+        let (x, y) = (a.num(), b.num());
+        let result = match op {
+            "add" => Object::Number(x + y),
+            "sub" => Object::Number(x - y),
+            "mul" => Object::Number(x * y),
+            "div" => Object::Number(x / y),
+            "idiv" => Object::Number((x / y).floor()),
+            "mod" => Object::Number(x % y),
+            "pow" => Object::Number(x.powf(y)),
+            "equal" | "notEqual" => {
+                let equal = if a.is_object() && b.is_object() { a == b } else { (x - y).abs() < 0.000001 };
+                bool_num(equal == (op == "equal"))
+            },
+            "strictEqual" => bool_num(a == b),
+            "land" => bool_num(x != 0.0 && y != 0.0),
+            "lessThan" => bool_num(x < y),
+            "lessThanEq" => bool_num(x <= y),
+            "greaterThan" => bool_num(x > y),
+            "greaterThanEq" => bool_num(x >= y),
+            "shl" => Object::Number(((x as i64).wrapping_shl(y as i64 as u32)) as f64),
+            "shr" => Object::Number(((x as i64).wrapping_shr(y as i64 as u32)) as f64),
+            "or" => Object::Number(((x as i64) | (y as i64)) as f64),
+            "and" => Object::Number(((x as i64) & (y as i64)) as f64),
+            "xor" => Object::Number(((x as i64) ^ (y as i64)) as f64),
+            "not" | "flip" => Object::Number(!(x as i64) as f64),
+            "max" => Object::Number(x.max(y)),
+            "min" => Object::Number(x.min(y)),
+            "angle" => Object::Number(y.atan2(x).to_degrees().rem_euclid(360.0)),
+            "len" => Object::Number(x.hypot(y)),
+            "noise" => Object::Number(self.noise(x, y)),
+            "abs" => Object::Number(x.abs()),
+            "log" => Object::Number(x.ln()),
+            "log10" => Object::Number(x.log10()),
+            "floor" => Object::Number(x.floor()),
+            "ceil" => Object::Number(x.ceil()),
+            "sqrt" => Object::Number(x.sqrt()),
+            "rand" => Object::Number(self.random() * x),
+            "sin" => Object::Number(x.to_radians().sin()),
+            "cos" => Object::Number(x.to_radians().cos()),
+            "tan" => Object::Number(x.to_radians().tan()),
+            _ => return Err(format!("unknown op {}", op)),
+        };
+        Ok(result)
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.executed / self.instructions_per_tick + self.waited
+    }
+
+    // hands out canned results from the scenario in order, sticking on the last one once they run out
+    fn next_call(&mut self, kind: &str, length: usize) -> Option<usize> {
+        let calls = self.calls.entry(kind.to_string()).or_insert(0);
+        *calls += 1;
+        if length == 0 { None } else { Some((*calls - 1).min(length - 1)) }
+    }
+
+    // memory of a cell or bank, anything else can't be read from or written to
+    fn memory_block(&mut self, object: &Object) -> Option<&mut Vec<f64>> {
+        match object {
+            Object::Building(name) => {
+                let size = memory_size(name)?;
+                Some(self.memory.entry(name.to_string()).or_insert_with(|| vec![0.0; size]))
+            },
+            _ => None,
+        }
+    }
+
+    // runs a single instruction
+    pub fn step(&mut self) -> Result<Status, String> {
+        if self.program.is_empty() {
+            return Ok(Status::Ended);
+        }
+        if self.counter >= self.program.len() {
+            self.counter = 0;
+        }
+
+        let words = self.program[self.counter].clone();
+        let arg = |index: usize| words.get(index).map(|word| word.as_str()).unwrap_or("null");
+        self.counts[self.counter] += 1;
+        self.counter += 1;
+        self.executed += 1;
+
+        match words[0].as_str() {
+            "set" => {
+                let value = self.get(arg(2));
+                self.set(arg(1), value);
+            },
+            "op" => {
+                let (a, b) = (self.get(arg(3)), self.get(arg(4)));
+                let result = self.op(arg(1), &a, &b)?;
+                self.set(arg(2), result);
+            },
+            "jump" => {
+                let target: usize = arg(1).parse().map_err(|_| format!("invalid jump target {}", arg(1)))?;
+                let jump = if arg(2) == "always" {
+                    true
+                } else {
+                    let (a, b) = (self.get(arg(3)), self.get(arg(4)));
+                    self.op(arg(2), &a, &b)?.num() != 0.0
+                };
+                if jump {
+                    self.counter = target;
+                }
+            },
+            "read" => {
+                let (cell, address) = (self.get(arg(2)), self.get(arg(3)).num() as i64);
+                // out of bounds reads give 0, same as in game
+                let value = self.memory_block(&cell).map(|memory| {
+                    if address >= 0 { memory.get(address as usize).copied().unwrap_or(0.0) } else { 0.0 }
+                });
+                if let Some(value) = value {
+                    self.set(arg(1), Object::Number(value));
+                }
+            },
+            "write" => {
+                let (value, cell, address) = (self.get(arg(1)).num(), self.get(arg(2)), self.get(arg(3)).num() as i64);
+                // and out of bounds writes are ignored
+                if let Some(memory) = self.memory_block(&cell) {
+                    if address >= 0 && (address as usize) < memory.len() {
+                        memory[address as usize] = value;
+                    }
+                }
+            },
+            "print" => {
+                let text = self.get(arg(1)).to_string();
+                for c in text.chars() {
+                    if self.text_buffer.chars().count() >= MAX_TEXT_BUFFER {
+                        break;
+                    }
+                    self.text_buffer.push(c);
+                }
+            },
+            "format" => {
+                // fills in whichever of {0} to {9} has the lowest number
+                let placeholder = (0..10).map(|digit| format!("{{{}}}", digit)).find(|placeholder| self.text_buffer.contains(placeholder.as_str()));
+                if let Some(placeholder) = placeholder {
+                    let value = self.get(arg(1)).to_string();
+                    self.text_buffer = self.text_buffer.replacen(&placeholder, &value, 1);
+                }
+            },
+            "printflush" => {
+                let text = std::mem::take(&mut self.text_buffer);
+                if let Object::Building(name) = self.get(arg(1)) {
+                    self.messages.insert(name, text);
+                }
+            },
+            "draw" => {
+                if self.draw_buffer.len() < MAX_DRAW_BUFFER {
+                    let args = words.iter().skip(2).map(|word| self.get(word).num()).collect();
+                    self.draw_buffer.push((arg(1).to_string(), args));
+                }
+            },
+            "drawflush" => {
+                let commands = std::mem::take(&mut self.draw_buffer);
+                if let Object::Building(name) = self.get(arg(1)) {
+                    if name.trim_end_matches(|c: char| c.is_ascii_digit()) == "display" {
+                        let size = self.display_size;
+                        let display = self.displays.entry(name.to_string()).or_insert_with(|| LogicDisplay::new(size));
+                        for (command, args) in commands.iter() {
+                            display.draw(command, args);
+                        }
+
+                        if let Some(output) = &self.draw_output {
+                            let filename = if output.frames {
+                                format!("{}/{}_{:04}.png", output.directory, name, display.flushes)
+                            } else {
+                                format!("{}/{}.png", output.directory, name)
+                            };
+                            display.save_png(&filename)?;
+                        }
+                        display.flushes += 1;
+                    }
+                }
+            },
+            "sensor" => {
+                let (target, tick) = (self.get(arg(2)), self.tick());
+                let value = self.scenario.sense(&target, arg(3), tick);
+                self.set(arg(1), value);
+            },
+            "getlink" => {
+                let index = self.get(arg(2)).num();
+                let link = if index >= 0.0 { self.scenario.links.get(index as usize) } else { None };
+                let value = link.map(|link| Object::Building(link.name.to_string())).unwrap_or(Object::Null);
+                self.set(arg(1), value);
+            },
+            "radar" => {
+                // radar target1 target2 target3 sort turret order result
+                let mut value = Object::Null;
+                if let Object::Building(name) = self.get(arg(5)) {
+                    let length = self.scenario.radar.get(&name).map(|results| results.len()).unwrap_or(0);
+                    if let Some(index) = self.next_call(&format!("radar {}", name), length) {
+                        value = self.scenario.object(&self.scenario.radar[&name][index]);
+                    }
+                }
+                self.set(arg(7), value);
+            },
+            "uradar" => {
+                let mut value = Object::Null;
+                if let Some(index) = self.next_call("uradar", self.scenario.uradar.len()) {
+                    value = self.scenario.object(&self.scenario.uradar[index]);
+                }
+                self.set(words.last().unwrap(), value);
+            },
+            "ulocate" => {
+                // the game writes out every argument, mlogp leaves off the ones a sub-instruction doesn't use
+                let outputs = if words.len() >= 9 || arg(1) == "building" { 4 } else { 3 };
+                let located = self.next_call("ulocate", self.scenario.ulocate.len()).map(|index| &self.scenario.ulocate[index]);
+                let mut values = match located {
+                    Some(located) => vec![
+                        Object::Number(located.x),
+                        Object::Number(located.y),
+                        Object::Number(if located.found { 1.0 } else { 0.0 }),
+                        located.building.as_ref().map(|name| self.scenario.object(name)).unwrap_or(Object::Null),
+                    ],
+                    None => vec![Object::Number(0.0), Object::Number(0.0), Object::Number(0.0), Object::Null],
+                };
+                values.truncate(outputs);
+                let start = words.len() - outputs;
+                for (word, value) in words[start..].iter().zip(values) {
+                    self.set(word, value);
+                }
+            },
+            "ubind" => {
+                // cycles through every unit of the given type, like the game does
+                let kind = self.get(arg(1));
+                let units: Vec<String> = self.scenario.units.iter()
+                    .map(|unit| Object::Unit(unit.name.to_string()))
+                    .filter(|unit| *unit == kind || self.scenario.sense(unit, "type", 0) == kind)
+                    .map(|unit| unit.to_string())
+                    .collect();
+                let calls = self.calls.entry(format!("ubind {}", kind)).or_insert(0);
+                self.bound_unit = if units.is_empty() {
+                    Object::Null
+                } else {
+                    *calls += 1;
+                    Object::Unit(units[(*calls - 1) % units.len()].to_string())
+                };
+            },
+            "ucontrol" => {
+                let tick = self.tick();
+                match arg(1) {
+                    "getBlock" => {
+                        let (x, y) = (self.get(arg(2)).num().floor() as i64, self.get(arg(3)).num().floor() as i64);
+                        let (block, building) = match self.scenario.blocks.iter().find(|block| block.x == x && block.y == y) {
+                            Some(found) => (self.scenario.object(&found.block), found.building.as_ref().map(|name| self.scenario.object(name)).unwrap_or(Object::Null)),
+                            None => (Object::Null, Object::Null),
+                        };
+                        self.set(arg(4), block);
+                        self.set(arg(5), building);
+                    },
+                    "within" => {
+                        let (x, y, radius) = (self.get(arg(2)).num(), self.get(arg(3)).num(), self.get(arg(4)).num());
+                        let unit_x = self.scenario.sense(&self.bound_unit, "x", tick).num();
+                        let unit_y = self.scenario.sense(&self.bound_unit, "y", tick).num();
+                        let within = self.bound_unit != Object::Null && (unit_x - x).hypot(unit_y - y) < radius;
+                        self.set(arg(5), Object::Number(if within { 1.0 } else { 0.0 }));
+                    },
+                    _ => (), // everything else just moves things around in the world, which isn't simulated
+                }
+            },
+            "control" => (), // same goes for controlling buildings
+            "end" => self.counter = self.program.len(),
+            "assert" => {
+                let (a, b) = (self.get(arg(2)), self.get(arg(3)));
+                if self.op(arg(1), &a, &b)?.num() == 0.0 {
+                    let mut message = format!("assertion failed: {} {} {}", arg(2), arg(1), arg(3));
+                    // show what the variables were, literals speak for themselves
+                    let values: Vec<String> = [(arg(2), a), (arg(3), b)].iter()
+                        .filter(|(word, value)| value.to_string() != *word && !word.starts_with('"'))
+                        .map(|(word, value)| format!("{} is {}", word, value))
+                        .collect();
+                    if !values.is_empty() {
+                        message = format!("{} ({})", message, values.join(", "));
+                    }
+                    return Ok(Status::AssertionFailed(message));
+                }
+            },
+            "noop" => (),
+            "wait" => self.waited += (self.get(arg(1)).num().max(0.0) * 60.0).ceil() as u64,
+            "stop" => {
+                self.counter -= 1;
+                return Ok(Status::Stopped);
+            },
+            "packcolor" => {
+                // same as the game, rgba8888 squeezed into the bits of a double
+                let channel = |index: usize| (self.get(arg(index)).num().clamp(0.0, 1.0) * 255.0) as u64;
+                let packed = (channel(2) << 24) | (channel(3) << 16) | (channel(4) << 8) | channel(5);
+                self.set(arg(1), Object::Number(f64::from_bits(packed)));
+            },
+            "lookup" => {
+                // blocks aren't in the database in id order, so those come out null
+                let category = match arg(1) { "item" => "Item", "liquid" => "Liquid", "unit" => "UnitType", _ => "" };
+                let index = self.get(arg(3)).num();
+                let content = database().names(category)
+                    .and_then(|names| if index >= 0.0 { names.get(index as usize).map(|name| name.to_string()) } else { None });
+                self.set(arg(2), content.map(|name| self.scenario.object(&name)).unwrap_or(Object::Null));
+            },
+            "setrate" => self.instructions_per_tick = self.get(arg(1)).num().max(1.0) as u64,
+            "sync" => (), // there's nobody to sync with
+            "getflag" => {
+                let flag = self.get(arg(2)).to_string();
+                self.set(arg(1), Object::Number(if self.flags.contains(&flag) { 1.0 } else { 0.0 }));
+            },
+            "setflag" => {
+                let flag = self.get(arg(1)).to_string();
+                if self.get(arg(2)).num() != 0.0 {
+                    self.flags.insert(flag);
+                } else {
+                    self.flags.remove(&flag);
+                }
+            },
+            name => return Err(format!("the interpreter doesn't support {} instructions", name)),
+        }
+
+        if self.counter >= self.program.len() {
+            self.counter = 0;
+            Ok(Status::Ended)
+        } else {
+            Ok(Status::Running)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(code: &str) -> Processor {
+        Processor::new(&code.lines().map(|line| line.to_string()).collect::<Vec<_>>())
+    }
+
+    // steps until the program ends or the budget runs out, the same way `mlogp run` does, and says whether it ended
+    fn run(processor: &mut Processor, budget: u64) -> bool {
+        while processor.executed < budget {
+            match processor.step().unwrap() {
+                Status::Running => (),
+                _ => return true,
+            }
+        }
+        false
+    }
+
+    fn number(processor: &Processor, name: &str) -> f64 {
+        processor.get(name).num()
+    }
+
+    #[test]
+    fn counter_writes_jump() {
+        let mut processor = load("set @counter 3\nset x 1\nend\nop add @counter @counter 1\nset x 2\nset y 3\nend");
+        assert!(run(&mut processor, 100));
+        assert_eq!(processor.get("x"), Object::Null);
+        assert_eq!(number(&processor, "y"), 3.0);
+
+        // a negative counter goes past the end, which ends the program and starts over from the top
+        let mut processor = load("set y 1\nset @counter -1\nset x 1");
+        assert_eq!(processor.step(), Ok(Status::Running));
+        assert_eq!(processor.step(), Ok(Status::Ended));
+        assert_eq!(processor.counter, 0);
+        assert_eq!(processor.get("x"), Object::Null);
+    }
+
+    #[test]
+    fn jumps_compare() {
+        let cases = [
+            ("equal", 2.0, 2.0, true), ("equal", 2.0, 3.0, false),
+            ("notEqual", 2.0, 3.0, true), ("notEqual", 2.0, 2.0, false),
+            ("lessThan", 1.0, 2.0, true), ("lessThan", 2.0, 2.0, false),
+            ("lessThanEq", 2.0, 2.0, true), ("lessThanEq", 3.0, 2.0, false),
+            ("greaterThan", 3.0, 2.0, true), ("greaterThan", 2.0, 2.0, false),
+            ("greaterThanEq", 2.0, 2.0, true), ("greaterThanEq", 1.0, 2.0, false),
+            ("strictEqual", 2.0, 2.0, true), ("strictEqual", 2.0, 3.0, false),
+        ];
+        for (comparison, a, b, taken) in cases {
+            let mut processor = load(&format!("jump 3 {} {} {}\nset x 1\nend\nset x 2", comparison, a, b));
+            run(&mut processor, 100);
+            assert_eq!(number(&processor, "x"), if taken { 2.0 } else { 1.0 }, "jump {} {} {}", comparison, a, b);
+        }
+
+        let mut processor = load("jump 2 always\nset x 1\nend");
+        run(&mut processor, 100);
+        assert_eq!(processor.get("x"), Object::Null);
+        // strictEqual doesn't count null as 0
+        let mut processor = load("jump 3 strictEqual y 0\nset x 1\nend\nset x 2");
+        run(&mut processor, 100);
+        assert_eq!(number(&processor, "x"), 1.0);
+    }
+
+    #[test]
+    fn memory_is_bounded() {
+        let mut processor = load("write 5 cell1 63\nwrite 6 cell1 64\nwrite 7 cell1 -1\nwrite 8 bank1 511\nread a cell1 63\nread b cell1 64\nread c cell1 -1\nread d bank1 511\nset e 9\nread e message1 0");
+        run(&mut processor, 100);
+        assert_eq!(processor.memory["cell1"].len(), 64);
+        assert_eq!(processor.memory["cell1"][63], 5.0);
+        assert_eq!(processor.memory["bank1"].len(), 512);
+        assert_eq!((number(&processor, "a"), number(&processor, "b"), number(&processor, "c"), number(&processor, "d")), (5.0, 0.0, 0.0, 8.0));
+        // reading from something that isn't memory leaves the variable alone
+        assert_eq!(number(&processor, "e"), 9.0);
+    }
+
+    #[test]
+    fn prints_flush_to_messages() {
+        let mut processor = load("print \"x = \"\nprint 1.5\nprint \" \"\nprint 2\nprintflush message1\nprint \"gone\"\nprintflush message2\nprintflush message2\nprint \"kept\"");
+        run(&mut processor, 100);
+        assert_eq!(processor.messages["message1"], "x = 1.5 2");
+        // flushing clears the buffer, even into the same message twice
+        assert_eq!(processor.messages["message2"], "");
+        assert_eq!(processor.text_buffer, "kept");
+
+        let mut processor = load("print \"0123456789\"\njump 0 lessThan @counter 999");
+        run(&mut processor, 200);
+        assert_eq!(processor.text_buffer.len(), MAX_TEXT_BUFFER);
+    }
+
+    #[test]
+    fn radar_looks_from_the_turret() {
+        let mut processor = load("radar enemy any any distance turret1 1 result\nradar enemy any any distance turret2 1 other");
+        processor.scenario.radar.insert("turret1".to_string(), vec!["@flare".to_string()]);
+        run(&mut processor, 100);
+        assert_eq!(processor.get("result"), Object::Content("flare".to_string()));
+        assert_eq!(processor.get("other"), Object::Null);
+    }
+
+    #[test]
+    fn budget_cuts_off_loops() {
+        let mut processor = load("op add i i 1\njump 0 always");
+        assert!(!run(&mut processor, 11));
+        assert_eq!(processor.executed, 11);
+        assert_eq!(number(&processor, "i"), 6.0);
+
+        // a program that ends inside the budget isn't cut off
+        let mut processor = load("set x 1\nend");
+        assert!(run(&mut processor, 11));
+        assert_eq!(processor.executed, 2);
+    }
+}
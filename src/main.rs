@@ -2,27 +2,63 @@ use std::fs;
 use std::fs::File;
 use std::collections::HashMap;
 use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use logos::Logos;
+use colored::*;
 
 pub mod token;
 pub mod instruction_compiler;
 pub mod instruction_list;
 pub mod value;
 pub mod misc;
+pub mod decompiler;
+pub mod interpreter;
+pub mod memory;
+pub mod display;
+pub mod testing;
+pub mod debugger;
+pub mod profiler;
+pub mod scenario;
+pub mod fuzz;
+pub mod content;
+pub mod interpolation;
+pub mod arrays;
+pub mod switch;
+pub mod enums;
+pub mod routines;
 
-use token::Token;
-use misc::error;
-use instruction_list::create_instructions;
+use token::{number_error, Token, BUILT_IN_VALUES};
+use misc::{error, error_no_line, internal_error, warning, warning_no_line};
+use instruction_list::{create_instructions, parse_target, Target};
+use instruction_compiler::InstructionCompiler;
+use value::Value;
+use decompiler::decompile;
+use interpreter::{Processor, Status};
+use memory::{load_memory, dump_memory};
+use display::DrawOutput;
+use testing::find_tests;
+use debugger::debug;
+use profiler::print_profile;
+use scenario::load_scenario;
+use fuzz::fuzz;
+use arrays::{find_arrays, element, Access, Storage};
+use switch::{find_switches, case_text, skip_line, Open};
+use enums::{find_enums, skip_declaration};
+use routines::{check_recursion, CallGraph};
 
 // command line arguments
 #[derive(Parser, Debug)]
 #[clap(author = "velleda", version, about = "very slightly higher level wrapper around mlog", long_about = "very slightly higher level wrapper around mlog. makes jump statements actually usable and will yell at you incomprehensibly if you make a mistake")]
+#[clap(subcommand_negates_reqs = true)]
 pub struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Input file
-    #[clap(short, long)]
-    input: String,
+    #[clap(short, long, required = true)]
+    input: Option<String>,
 
     /// Output file (setting to stdout will send output to stdout)
     #[clap(short, long, default_value = "stdout")]
@@ -31,48 +67,191 @@ pub struct Args {
     /// Add debug comments to generated code
     #[clap(short, long)]
     debugcomments: bool,
+
+    /// Check array indexes while the program runs, jumping to this label when one is out of bounds
+    #[clap(long, value_name = "LABEL")]
+    bounds_check: Option<String>,
+
+    /// Instruction set to use instead of the bundled one (.json, otherwise toml)
+    #[clap(long)]
+    isa: Option<String>,
+
+    /// Version of the game to compile for, which decides what instructions are available
+    #[clap(short, long, default_value = "v7", possible_values = ["v6", "v7", "v8"])]
+    target: String,
+
+    /// Compile for a world processor, which allows the world processor instructions
+    #[clap(short, long)]
+    world: bool,
 }
 
-pub fn main() {
-    let args = Args::parse();
-    //println!("{:?}", args);
-    let string = fs::read_to_string(&args.input).unwrap();
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Convert plain mlog back into mlogp, with labels in place of jump addresses
+    Decompile,
+
+    /// Compile and run the program in a virtual logic processor, printing message blocks at the end
+    Run {
+        /// Maximum number of instructions to run
+        #[clap(short, long, default_value = "1000000")]
+        budget: u64,
+
+        /// Keep going after the program ends (like the game does) until the budget runs out
+        #[clap(short, long)]
+        repeat: bool,
+
+        /// File with the starting contents of memory cells and banks (.json, otherwise csv)
+        #[clap(short, long)]
+        memory: Option<String>,
+
+        /// File to save the final contents of memory cells and banks to, in the same formats (setting to stdout will send it to stdout as csv)
+        #[clap(long)]
+        dump_memory: Option<String>,
+
+        /// Size of the displays draw instructions are rendered to (80 for a logic display, 176 for a large one)
+        #[clap(long, default_value = "80", possible_values = ["80", "176"])]
+        display_size: usize,
+
+        /// Directory to save a png of each display to whenever it's flushed (nothing is saved without it)
+        #[clap(long)]
+        draw_dir: Option<String>,
+
+        /// Save every drawflush in --draw-dir as its own numbered png (display1_0000.png, display1_0001.png, ...) instead of overwriting one
+        #[clap(long)]
+        frames: bool,
+
+        /// Kind of processor to run as, which decides how many instructions run each tick
+        #[clap(short, long, default_value = "logic", possible_values = ["micro", "logic", "hyper"])]
+        processor: String,
+
+        /// Show how many times each line and routine ran, and roughly how long the program took
+        #[clap(long)]
+        profile: bool,
+
+        /// Scenario file (.json, otherwise toml) describing linked buildings, units and radar results
+        #[clap(short, long)]
+        scenario: Option<String>,
+    },
+
+    /// Run every test "name" ... endtest block in the program, along with the routines it calls
+    Test {
+        /// Maximum number of instructions each test can run
+        #[clap(short, long, default_value = "100000")]
+        budget: u64,
+
+        /// Scenario file (.json, otherwise toml) describing linked buildings, units and radar results
+        #[clap(short, long)]
+        scenario: Option<String>,
+    },
+
+    /// Step through the program in the interpreter, with breakpoints on source lines
+    Debug {
+        /// Maximum number of instructions to run on each continue before stopping anyway
+        #[clap(short, long, default_value = "1000000")]
+        budget: u64,
+
+        /// File with the starting contents of memory cells and banks (.json, otherwise csv)
+        #[clap(short, long)]
+        memory: Option<String>,
+
+        /// Scenario file (.json, otherwise toml) describing linked buildings, units and radar results
+        #[clap(short, long)]
+        scenario: Option<String>,
+    },
+
+    /// Compile random programs and check the emitted addresses and the interpreter agree with what they should do (doesn't need an input file)
+    Fuzz {
+        /// Seed for the first program, each one after that uses the next seed (defaults to the current time)
+        #[clap(short, long)]
+        seed: Option<u64>,
+
+        /// Number of programs to check
+        #[clap(short, long, default_value = "1000")]
+        count: usize,
+
+        /// Most statements in the main program and each routine
+        #[clap(long, default_value = "20")]
+        size: usize,
+    },
+}
+
+// writes finished code to wherever the user asked for it
+fn write_output(args: &Args, mut code: Vec<String>) {
+    code.push("".to_string()); // add another newline for good measure
+
+    let code_string = code.join("\n");
+
+    if args.output == "stdout" {
+        println!("{}", code_string);
+    } else {
+        let mut file = File::create(&args.output).expect("unable to create file");
+        file.write_all(code_string.as_bytes()).expect("unable to write");
+        println!("Saved to {}", &args.output);
+    }
+}
+
+// compiled mlog, along with which line of the source each instruction came from and which routine it's in
+pub struct Compiled {
+    pub code: Vec<String>,
+    pub lines: Vec<u32>,
+    pub scopes: Vec<Option<String>>,
+    pub labels: HashMap<String, u32>,
+    pub routines: HashMap<String, u32>,
+}
+
+// pass one and pass two disagreeing about where something goes, which is a bug in mlogp rather than the program
+#[derive(Debug)]
+pub struct AddressError {
+    pub message: String,
+    pub line: u32,
+}
+
+// turns mlogp source into mlog. tests are left out, unless one is picked to be compiled (along with the routines it calls) instead of the main program
+pub fn compile(string: &str, filename: &str, instructions: &[Box<dyn InstructionCompiler>], debug: bool, bounds_check: Option<&str>, test: Option<usize>) -> Result<Compiled, AddressError> {
+    let suite = find_tests(string, filename);
+    let source = match test {
+        Some(index) => suite.select(string, index),
+        None => suite.strip(string),
+    };
+    let string = source.as_str();
+    let arrays = find_arrays(string, filename);
+    let enums = find_enums(string, filename);
+    let mut switches = find_switches(string, filename, &enums);
 
     // first pass: find label names and locations, variables
-    let mut lex = Token::lexer(&string);
+    let mut lex = Token::lexer_with_extras(string, enums.clone());
     let mut inst_counter = 0;
     let mut line_count = 1;
     let mut labels: HashMap<String, u32> = HashMap::new();
     let mut routines: HashMap<String, u32> = HashMap::new();
+    let (mut label_lines, mut routine_lines) = (HashMap::new(), HashMap::new()); // where each was defined
+    let mut calls: CallGraph = Vec::new(); // which routines each routine calls, for check_recursion
+    let mut in_routine = false;
     let mut variables: Vec<String> = Vec::new();
+    for (name, array) in arrays.iter() {
+        if let Storage::Registers = array.storage {
+            variables.extend((0..array.size).map(|index| element(name, index)));
+        }
+    }
+    let has_format = instructions.iter().any(|inst| inst.as_instruction().map(|inst| inst.name == "format").unwrap_or(false))
+        && interpolation::format_is_safe(string); // for print "{name}"
 
+    let mut open_switches: Vec<Open> = Vec::new();
+    let mut switch_count = 0;
     while let Some(token) = lex.next() {
         //println!("pass 1 {:?}", token);
         let mut skip_to_next = true;
         let slice = lex.slice();
+        let token = outside_switch(token, &open_switches);
+        let token = if arrays::is_access(&lex) { token.as_name(lex.slice()) } else { token }; // max = buf[i] is still an array access
         match token {
-            Token::InstWrite | Token::InstDraw | Token::InstDrawFlush | 
-            Token::InstPrint | Token::InstPrintFlush | Token::InstControl |
-            Token::InstEnd | Token::InstJump | Token::InstUnitBind |
-            Token::InstUnitRadar | Token::InstNoOp | Token::InstGoto |
             Token::InstReturn => inst_counter += 1, // emits one instruction
 
-            Token::InstGosub | Token::InstGosubCond => inst_counter += 2, // emits two instructions
-
-            Token::InstRead | Token::InstGetLink | Token::InstSensor |
-            Token::InstSet => {
-                inst_counter += 1;
-                //println!("token {:?} has var", token);
-                match lex.next() {
-                    Some(Token::Name(var)) => {
-                        if !variables.contains(&var) {
-                            //println!("token {:?}, var {}", token, var);
-                            variables.push(var);
-                        }
-                    },
-                    Some(Token::SysVar(_)) => (),
-                    Some(_) => { error(&format!("unexpected token {} after {}", lex.slice(), slice), &args.input, line_count); std::process::exit(1); },
-                    None => { error(&format!("unexpected EOF after {}", slice), &args.input, line_count); std::process::exit(1); },
+            Token::InstGosub | Token::InstGosubCond => {
+                inst_counter += 2; // emits two instructions
+                let mut peek = lex.clone();
+                if let (Some((_, calls)), Some(Token::Name(callee))) = (calls.last_mut().filter(|_| in_routine), peek.next().map(|token| token.as_name(peek.slice()))) {
+                    calls.push((callee, line_count));
                 }
             },
 
@@ -80,145 +259,100 @@ pub fn main() {
                 inst_counter += 1;
                 lex.next();
                 //println!("token {:?} has var", token);
-                match lex.next() {
+                match lex.next().map(|token| token.as_name(lex.slice())) {
                     Some(Token::Name(var)) => {
                         if !variables.contains(&var) {
                             //println!("token {:?}, var {}", token, var);
                             variables.push(var);
                         }
                     },
-                    Some(Token::SysVar(_)) => (),
-                    Some(_) => { error(&format!("unexpected token {} after {}", lex.slice(), slice), &args.input, line_count); std::process::exit(1); },
-                    None => { error(&format!("unexpected EOF after {}", slice), &args.input, line_count); std::process::exit(1); },
+                    Some(Token::SysVar(_)) | Some(Token::Bool(_)) => (), // pass two says what's wrong with a bool
+                    Some(_) => { error(&format!("unexpected token {} after {}", lex.slice(), slice), filename, line_count); std::process::exit(1); },
+                    None => { error(&format!("unexpected EOF after {}", slice), filename, line_count); std::process::exit(1); },
                 }
             },
 
-            Token::InstRadar => {
-                inst_counter += 1;
-                for _ in 0..5 {
-                    lex.next();
-                }
-                //println!("token {:?} has var", token);
-                match lex.next() {
-                    Some(Token::Name(var)) => {
-                        if !variables.contains(&var) {
-                            //println!("token {:?}, var {}", token, var);
-                            variables.push(var);
-                        }
-                    },
-                    Some(Token::SysVar(_)) => (),
-                    Some(_) => { error(&format!("unexpected token {} after {}", lex.slice(), slice), &args.input, line_count); std::process::exit(1); },
-                    None => { error(&format!("unexpected EOF after {}", slice), &args.input, line_count); std::process::exit(1); },
+            Token::Label(name) => {
+                check_declared_name("label", &name, filename, line_count);
+                check_defined_once("label", &name, &mut label_lines, filename, line_count);
+                labels.insert(name, inst_counter);
+            },
+            Token::Subroutine(name) => {
+                check_declared_name("routine", &name, filename, line_count);
+                check_defined_once("routine", &name, &mut routine_lines, filename, line_count);
+                calls.push((name.clone(), Vec::new()));
+                in_routine = true;
+                routines.insert(name, inst_counter);
+            },
+            Token::Newline => { skip_to_next = false; line_count += 1; },
+
+            Token::InstEndRoutine => in_routine = false,
+            Token::Array | Token::Regs => (), // already found by find_arrays
+            Token::Enum => line_count += skip_declaration(&mut lex), // already found by find_enums
+
+            Token::Switch => {
+                inst_counter += switches[switch_count].dispatch_count();
+                open_switches.push(Open::new(switch_count));
+                switch_count += 1;
+            },
+            Token::Case | Token::Default => {
+                let open = open_switches.last_mut().unwrap();
+                if open.next_section(&switches) {
+                    inst_counter += 1; // the case before jumps to the end
                 }
+                open.set_address(&mut switches, inst_counter);
+            },
+            Token::Fallthrough => (),
+            Token::EndSwitch => {
+                let open = open_switches.pop().unwrap();
+                switches[open.switch()].end = inst_counter;
             },
 
-            Token::InstUnitControl => {
-                inst_counter += 1;
-                match lex.next() {
-                    Some(Token::SubInstGetBlock) => {
-                        lex.next();
-                        lex.next();
-                        lex.next();
-                        //println!("token {:?} has var", token);
-                        for _ in 0..2 {
-                            match lex.next() {
-                                Some(Token::Name(var)) => {
-                                    if !variables.contains(&var) {
-                                        //println!("token {:?}, var {}", token, var);
-                                        variables.push(var);
-                                    }
-                                },
-                                Some(Token::SysVar(_)) => (),
-                                Some(_) => { error(&format!("unexpected token {} after {}", lex.slice(), slice), &args.input, line_count); std::process::exit(1); },
-                                None => { error(&format!("unexpected EOF after {}", slice), &args.input, line_count); std::process::exit(1); },
-                            }
-                        }
-                    },
-                    Some(Token::SubInstWithin) => {
-                        lex.next();
-                        lex.next();
-                        //println!("token {:?} has var", token);
-                        match lex.next() {
-                            Some(Token::Name(var)) => {
-                                if !variables.contains(&var) {
-                                    //println!("token {:?}, var {}", token, var);
-                                    variables.push(var);
-                                }
-                            },
-                            Some(Token::SysVar(_)) => (),
-                            Some(_) => { error(&format!("unexpected token {} after {}", lex.slice(), slice), &args.input, line_count); std::process::exit(1); },
-                            None => { error(&format!("unexpected EOF after {}", slice), &args.input, line_count); std::process::exit(1); },
-                        }
-                    },
-                    _ => (),
+            Token::Name(name) if arrays::is_access(&lex) => {
+                let access = Access::parse(&mut lex, name, &arrays, filename, line_count);
+                inst_counter += access.count(&arrays, bounds_check.is_some());
+                if let Some(variable) = access.output() {
+                    if !variables.contains(variable) {
+                        variables.push(variable.to_string());
+                    }
                 }
-            }
+            },
 
-            Token::InstUnitLocate => {
-                inst_counter += 1;
-                match lex.next() {
-                    Some(Token::SubInstOre) => {
-                        lex.next();
-                        //println!("token {:?} has var", token);
-                        for _ in 0..3 {
-                            match lex.next() {
-                                Some(Token::Name(var)) => {
-                                    if !variables.contains(&var) {
-                                        //println!("token {:?}, var {}", token, var);
-                                        variables.push(var);
-                                    }
-                                },
-                                Some(Token::SysVar(_)) => (),
-                                Some(_) => { error(&format!("unexpected token {} after {}", lex.slice(), slice), &args.input, line_count); std::process::exit(1); },
-                                None => { error(&format!("unexpected EOF after {}", slice), &args.input, line_count); std::process::exit(1); },
-                            }
-                        }
-                    },
-                    Some(Token::SubInstBuilding) => {
-                        lex.next();
-                        lex.next();
-                        //println!("token {:?} has var", token);
-                        for _ in 0..4 {
-                            match lex.next() {
-                                Some(Token::Name(var)) => {
-                                    if !variables.contains(&var) {
-                                        //println!("token {:?}, var {}", token, var);
-                                        variables.push(var);
-                                    }
-                                },
-                                Some(Token::SysVar(_)) => (),
-                                Some(_) => { error(&format!("unexpected token {} after {}", lex.slice(), slice), &args.input, line_count); std::process::exit(1); },
-                                None => { error(&format!("unexpected EOF after {}", slice), &args.input, line_count); std::process::exit(1); },
-                            }
+            _ => {
+                let inst = match instructions.iter().find(|inst| inst.name() == slice) {
+                    Some(inst) => inst,
+                    None => {
+                        match instructions.iter().find(|inst| inst.name().eq_ignore_ascii_case(slice)) {
+                            Some(inst) => error(&format!("unknown instruction {}, did you mean {}?", slice, inst.name()), filename, line_count),
+                            None => error(&number_error(&lex).unwrap_or(format!("unexpected token {}", slice)), filename, line_count),
                         }
+                        std::process::exit(1);
                     },
-                    Some(Token::SubInstSpawn) => {
-                        //println!("token {:?} has var", token);
-                        for _ in 0..3 {
-                            match lex.next() {
-                                Some(Token::Name(var)) => {
-                                    if !variables.contains(&var) {
-                                        //println!("token {:?}, var {}", token, var);
-                                        variables.push(var);
-                                    }
-                                },
-                                Some(Token::SysVar(_)) => (),
-                                Some(_) => { error(&format!("unexpected token {} after {}", lex.slice(), slice), &args.input, line_count); std::process::exit(1); },
-                                None => { error(&format!("unexpected EOF after {}", slice), &args.input, line_count); std::process::exit(1); },
-                            }
-                        }
+                };
+                inst_counter += interpolation::count(&lex, slice, has_format).unwrap_or(1) as u32;
+
+                // instructions write to variables through their output arguments, which is how variables get declared
+                let inst = match (inst.as_instruction(), inst.as_group()) {
+                    (Some(inst), _) => Some(inst),
+                    (_, Some(group)) => match lex.next() {
+                        Some(Token::Newline) => { skip_to_next = false; line_count += 1; None },
+                        _ => group.sub_instructions.iter().find(|sub| sub.name == lex.slice()),
                     },
-                    _ => (),
+                    _ => None,
+                };
+                let arguments = inst.map(|inst| inst.arguments.as_slice()).unwrap_or_default();
+                let mut index = 0;
+                while index < arguments.len() {
+                    match lex.next().map(|token| token.as_name(lex.slice())) {
+                        Some(Token::Newline) => { skip_to_next = false; line_count += 1; break; },
+                        None => break,
+                        Some(Token::Color(_)) => index += inst.map(|inst| inst.color_width(index)).unwrap_or(0).max(1) - 1, // might fill in a few arguments
+                        Some(Token::Name(var)) if matches!(arguments[index], Value::Variable(_)) && !variables.contains(&var) => variables.push(var),
+                        _ => (),
+                    }
+                    index += 1;
                 }
-            }
-
-            Token::Label(name) => { labels.insert(name, inst_counter); },
-            Token::Subroutine(name) => { routines.insert(name, inst_counter); },
-            Token::Newline => { skip_to_next = false; line_count += 1; },
-
-            Token::InstEndRoutine => (),
-
-            _ => { error(&format!("unexpected token {}", slice), &args.input, line_count); std::process::exit(1); },
+            },
         }
         if skip_to_next {
             loop {
@@ -236,67 +370,318 @@ pub fn main() {
     //println!("labels: {:?}", labels);
     //println!("variables: {:?}", variables);
 
-    let instructions = create_instructions();
+    check_recursion(&calls, filename);
 
     // 2nd pass: fill out code
-    lex = Token::lexer(&string); // do 'gain
+    lex = Token::lexer_with_extras(string, enums.clone()); // do 'gain
     let mut code: Vec<String> = Vec::new();
     let mut current_subroutine: Option<String> = None;
     line_count = 1;
     let mut label_debug_name: Option<String> = None;
+    let mut lines: Vec<u32> = Vec::new();
+    let mut scopes: Vec<Option<String>> = Vec::new();
+    let bounds_handler = bounds_check.map(|label| match labels.get(label) {
+        Some(address) => *address,
+        None => { error_no_line(&format!("couldn't find label {} to jump to when an array index is out of bounds", label), filename); std::process::exit(1); },
+    });
+    let mut switch_count = 0;
     while let Some(token) = lex.next() {
         //println!("pass 2 token {:?}", token);
+        let token = outside_switch(token, &open_switches);
+        let token = if arrays::is_access(&lex) { token.as_name(lex.slice()) } else { token }; // max = buf[i] is still an array access
         match token {
-            Token::Label(name) => label_debug_name = Some(format!("label {}", name)), // we dont want these in the finished code
-            Token::Subroutine(sub) => { label_debug_name = Some(format!("subroutine {}", sub)); current_subroutine = Some(sub) },
+            Token::Label(name) => {
+                check_address("label", &name, labels[&name], code.len(), line_count)?;
+                label_debug_name = Some(format!("label {}", name)); // we dont want these in the finished code
+            },
+            Token::Subroutine(sub) => {
+                check_address("routine", &sub, routines[&sub], code.len(), line_count)?;
+                label_debug_name = Some(format!("subroutine {}", sub));
+                current_subroutine = Some(sub);
+            },
             Token::InstReturn => {
                 if let Some(label) = &current_subroutine {
-                    if args.debugcomments {
+                    if debug {
                         code.push(format!("set @counter {}Return # return from {}", label, label));
                     } else {
                         code.push(format!("set @counter {}Return", label));
                     }
                 } else {
-                    error("can't return outside of a subroutine!", &args.input, line_count);
+                    error("can't return outside of a subroutine!", filename, line_count);
                     std::process::exit(1);
                 }
             },
             Token::InstEndRoutine => current_subroutine = None,
             Token::Newline => line_count += 1,
+            Token::Array | Token::Regs => skip_line(&mut lex),
+            Token::Color(_) => {
+                // the instruction before it already has all its arguments, so this was meant as a comment
+                warning(&format!("{} comes after everything the instruction takes, so it's a comment rather than a color (put a space after the # to make that clear)", lex.slice()), filename, line_count);
+                skip_line(&mut lex);
+            },
+            Token::Enum => line_count += skip_declaration(&mut lex),
+            Token::Switch => {
+                let subject = match Value::Any("value".to_string()).compile(&mut lex, &variables, false, filename, line_count) {
+                    Some(subject) => subject,
+                    None => { println!("{} switch is defined as: switch value (any)", "note:".bold()); std::process::exit(1); },
+                };
+                let start = code.len();
+                code.append(&mut switches[switch_count].dispatch(&subject, debug));
+                annotate_label(&mut code, start, &mut label_debug_name, debug);
+                open_switches.push(Open::new(switch_count));
+                switch_count += 1;
+            },
+            Token::Case | Token::Default => {
+                let name = match token {
+                    Token::Case => format!("case {}", case_text(&mut lex, filename, line_count)),
+                    _ => "default".to_string(),
+                };
+                let open = open_switches.last_mut().unwrap();
+                if open.next_section(&switches) {
+                    code.push(format!("jump {} always{}", switches[open.switch()].end, if debug { " # endswitch" } else { "" }));
+                }
+                check_address("switch", &name, open.address(&switches), code.len(), line_count)?;
+                label_debug_name = Some(name);
+            },
+            Token::Fallthrough => (),
+            Token::EndSwitch => {
+                let open = open_switches.pop().unwrap();
+                check_address("switch", "end", switches[open.switch()].end, code.len(), line_count)?;
+            },
+            Token::Name(name) if arrays::is_access(&lex) => {
+                let access = Access::parse(&mut lex, name, &arrays, filename, line_count);
+                let start = code.len();
+                code.append(&mut access.compile(&mut lex, &arrays, &variables, bounds_handler, start, filename, line_count, debug));
+                annotate_label(&mut code, start, &mut label_debug_name, debug);
+            },
             _ => {
                 let mut found_inst = false;
                 for inst in instructions.iter() {
-                    if token == inst.get_token() {
-                        code.append(&mut inst.compile(&mut lex, &variables, &labels, &routines, &args.input, line_count, args.debugcomments, &current_subroutine, code.len()));
-                        if let Some(name) = &label_debug_name {
-                            if args.debugcomments {
-                                let string = code.pop().unwrap();
-                                code.push(format!("{} # {}", string, name));
-                            }
-                            
-                            label_debug_name = None;
-                        }
+                    if inst.name() == lex.slice() {
+                        let mut compiled = match interpolation::compile(&mut lex, inst.name(), &variables, has_format, filename, line_count) {
+                            Some(compiled) => compiled,
+                            None => inst.compile(&mut lex, &variables, &labels, &routines, filename, line_count, debug, &current_subroutine, code.len()),
+                        };
+                        let start = code.len();
+                        code.append(&mut compiled);
+                        annotate_label(&mut code, start, &mut label_debug_name, debug);
                         found_inst = true;
                         break;
                     }
                 }
                 if !found_inst {
-                    error(&format!("unexpected token {}", lex.slice()), &args.input, line_count);
+                    error(&number_error(&lex).unwrap_or(format!("unexpected token {}", lex.slice())), filename, line_count);
                     std::process::exit(1);
                 }
             },
         }
+        lines.resize(code.len(), line_count);
+        scopes.resize(code.len(), current_subroutine.clone());
     }
 
-    code.push("".to_string()); // add another newline for good measure
+    if code.len() != inst_counter as usize {
+        return Err(AddressError { message: format!("the first pass counted {} instructions, but {} were emitted", inst_counter, code.len()), line: line_count });
+    }
 
-    let code_string = code.join("\n");
+    Ok(Compiled { code, lines, scopes, labels, routines })
+}
 
-    if args.output == "stdout" {
-        println!("{}", code_string);
-    } else {
-        let mut file = File::create(&args.output).expect("unable to create file");
-        file.write_all(code_string.as_bytes()).expect("unable to write");
-        println!("Saved to {}", &args.output);
+// default: is only special in a switch, anywhere else it's a label
+fn outside_switch(token: Token, open_switches: &[Open]) -> Token {
+    match token {
+        Token::Default if open_switches.is_empty() => Token::Label("default".to_string()),
+        token => token,
+    }
+}
+
+// puts the name of the label just before some code in a comment on it, when debug comments are on
+fn annotate_label(code: &mut [String], start: usize, label_debug_name: &mut Option<String>, debug: bool) {
+    if let Some(name) = label_debug_name.take() {
+        if debug {
+            code[start].push_str(&format!(" # {}", name));
+        }
+    }
+}
+
+// true, false and null always mean themselves, so nothing else can be called that
+fn check_declared_name(kind: &str, name: &str, filename: &str, line: u32) {
+    if BUILT_IN_VALUES.contains(&name) {
+        error(&format!("{} is a built in value, so it can't be used as a {} name", name, kind), filename, line);
+        std::process::exit(1);
+    }
+}
+
+// makes sure pass one's address for a label or routine is where its code actually ended up,
+// otherwise every jump to it would go to the wrong place
+fn check_address(kind: &str, name: &str, counted: u32, emitted: usize, line: u32) -> Result<(), AddressError> {
+    if counted as usize != emitted {
+        return Err(AddressError { message: format!("{} {} was counted at address {} in the first pass, but its code starts at {}", kind, name, counted, emitted), line });
     }
+    Ok(())
+}
+
+// a second label (or routine) with the same name would leave jumps to it going to whichever came last
+fn check_defined_once(kind: &str, name: &str, defined: &mut HashMap<String, u32>, filename: &str, line: u32) {
+    if let Some(first) = defined.insert(name.to_string(), line) {
+        error(&format!("{} {} is defined on line {} and again on line {}", kind, name, first, line), filename, line);
+        std::process::exit(1);
+    }
+}
+
+// an address mismatch can only be reported, since there's no way to tell which pass got it right
+pub fn or_internal_error(compiled: Result<Compiled, AddressError>, filename: &str) -> Compiled {
+    compiled.unwrap_or_else(|mismatch| internal_error(&mismatch.message, filename, mismatch.line))
+}
+
+// sets up a processor for running a compiled program, loading any files it needs
+fn create_processor(compiled: &Compiled, memory: &Option<String>, scenario: &Option<String>) -> Processor {
+    let mut processor = Processor::new(&compiled.code);
+    if let Some(filename) = memory {
+        match load_memory(filename) {
+            Ok(memory) => processor.memory = memory,
+            Err(message) => { error_no_line(&message, filename); std::process::exit(1); },
+        }
+    }
+    if let Some(filename) = scenario {
+        match load_scenario(filename) {
+            Ok(scenario) => processor.scenario = scenario,
+            Err(message) => { error_no_line(&message, filename); std::process::exit(1); },
+        }
+    }
+    processor
+}
+
+pub fn main() {
+    let args = Args::parse();
+    //println!("{:?}", args);
+
+    let instructions = match parse_target(&args.target).and_then(|version| create_instructions(args.isa.as_deref(), Target { version, world: args.world })) {
+        Ok(instructions) => instructions,
+        Err(message) => {
+            error_no_line(&message, args.isa.as_deref().unwrap_or("bundled instruction set"));
+            std::process::exit(1);
+        },
+    };
+
+    if let Some(Command::Fuzz { seed, count, size }) = args.command {
+        let seed = seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0));
+        println!("fuzzing from seed {}", seed);
+        if !fuzz(&instructions, seed, count, size) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let input = match &args.input {
+        Some(input) => input.as_str(),
+        None => {
+            println!("{} an input file is required (--input <INPUT>)", "error:".red().bold());
+            std::process::exit(1);
+        },
+    };
+    let string = fs::read_to_string(input).unwrap();
+
+    if let Some(Command::Decompile) = args.command {
+        let code = decompile(&string, &instructions, input);
+        write_output(&args, code);
+        return;
+    }
+
+    if let Some(Command::Test { budget, scenario }) = &args.command {
+        let budget = *budget;
+        let suite = find_tests(&string, input);
+        let mut failed = 0;
+        for (index, test) in suite.tests.iter().enumerate() {
+            let compiled = or_internal_error(compile(&string, input, &instructions, args.debugcomments, args.bounds_check.as_deref(), Some(index)), input);
+            let mut processor = create_processor(&compiled, &None, scenario);
+            // start at the first instruction of the test itself, rather than any routines before it
+            processor.counter = compiled.lines.iter().position(|line| *line > test.start).unwrap_or(0);
+
+            let mut failure = Some((format!("test didn't finish within {} instructions", budget), test.start));
+            while processor.executed < budget {
+                let address = processor.counter;
+                match processor.step() {
+                    Ok(Status::Running) => (),
+                    Ok(Status::Ended | Status::Stopped) => { failure = None; break; },
+                    Ok(Status::AssertionFailed(message)) => { failure = Some((message, compiled.lines[address])); break; },
+                    Err(message) => { failure = Some((message, compiled.lines[address])); break; },
+                }
+            }
+
+            if let Some((message, line)) = failure {
+                println!("test {} ... {}", test.name, "FAILED".red().bold());
+                error(&message, input, line);
+                failed += 1;
+            } else {
+                println!("test {} ... {}", test.name, "ok".green());
+            }
+        }
+
+        println!();
+        let result = if failed == 0 { "ok".green() } else { "FAILED".red().bold() };
+        println!("test result: {}. {} passed; {} failed", result, suite.tests.len() - failed, failed);
+        if failed > 0 {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let compiled = or_internal_error(compile(&string, input, &instructions, args.debugcomments, args.bounds_check.as_deref(), None), input);
+
+    if let Some(Command::Run { budget, repeat, memory, dump_memory: dump, display_size, draw_dir, frames, processor: kind, profile, scenario }) = &args.command {
+        let (budget, repeat) = (*budget, *repeat);
+        let mut processor = create_processor(&compiled, memory, scenario);
+        processor.instructions_per_tick = match kind.as_str() {
+            "micro" => 2,
+            "hyper" => 25,
+            _ => 8,
+        };
+        processor.display_size = *display_size;
+        processor.draw_output = draw_dir.as_ref().map(|directory| DrawOutput { directory: directory.to_string(), frames: *frames });
+        if *frames && draw_dir.is_none() {
+            warning_no_line("--frames does nothing without --draw-dir to save them to", input);
+        }
+        let mut ended = false;
+        while processor.executed < budget {
+            let address = processor.counter;
+            match processor.step() {
+                Ok(Status::Ended) if !repeat || processor.program.is_empty() => { ended = true; break; },
+                Ok(Status::Stopped) => { ended = true; break; },
+                Ok(Status::AssertionFailed(message)) => { error(&message, input, compiled.lines[address]); std::process::exit(1); },
+                Ok(_) => (),
+                Err(message) => { error(&message, input, compiled.lines[address]); std::process::exit(1); },
+            }
+        }
+        if !ended && !repeat {
+            warning_no_line(&format!("ran out of instruction budget ({} instructions) before the program ended", budget), input);
+        }
+
+        for (name, text) in processor.messages.iter() {
+            println!("{}\n{}", format!("{}:", name).bold(), text);
+        }
+
+        if let Some(filename) = dump {
+            if filename == "stdout" {
+                print!("{}", dump_memory(&processor.memory, false));
+            } else {
+                let mut file = File::create(filename).expect("unable to create file");
+                file.write_all(dump_memory(&processor.memory, filename.ends_with(".json")).as_bytes()).expect("unable to write");
+                println!("Saved memory to {}", filename);
+            }
+        }
+
+        if *profile {
+            println!();
+            print_profile(&compiled, &processor, &string, &format!("{} processor", kind));
+        }
+        return;
+    }
+
+    if let Some(Command::Debug { budget, memory, scenario }) = &args.command {
+        let mut processor = create_processor(&compiled, memory, scenario);
+        debug(&compiled, &mut processor, &string, *budget);
+        return;
+    }
+
+    write_output(&args, compiled.code);
 }
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::fs;
+
+// memory cells hold 64 numbers, memory banks hold 512
+pub fn memory_size(name: &str) -> Option<usize> {
+    let kind = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    match kind {
+        "cell" => Some(64),
+        "bank" => Some(512),
+        _ => None,
+    }
+}
+
+// pads out loaded values to the full size of the memory block
+fn fill(name: &str, mut values: Vec<f64>) -> Result<Vec<f64>, String> {
+    match memory_size(name) {
+        Some(size) if values.len() > size => Err(format!("{} only has room for {} values, got {}", name, size, values.len())),
+        Some(size) => {
+            values.resize(size, 0.0);
+            Ok(values)
+        },
+        None => Err(format!("{} isn't a memory cell or bank", name)),
+    }
+}
+
+// loads initial memory contents, either from json (an object mapping names to arrays of numbers) or csv (one memory block per row, name first)
+pub fn load_memory(filename: &str) -> Result<HashMap<String, Vec<f64>>, String> {
+    let string = fs::read_to_string(filename).map_err(|err| format!("couldn't read file: {}", err))?;
+    let mut memory = HashMap::new();
+
+    if filename.ends_with(".json") {
+        let json: serde_json::Value = serde_json::from_str(&string).map_err(|err| format!("invalid json: {}", err))?;
+        let blocks = json.as_object().ok_or("expected an object mapping memory names to arrays of numbers")?;
+        for (name, values) in blocks.iter() {
+            let values = values.as_array().ok_or(format!("expected an array of numbers for {}", name))?;
+            let values = values.iter()
+                .map(|value| if value.is_null() { Some(0.0) } else { value.as_f64() })
+                .collect::<Option<Vec<f64>>>()
+                .ok_or(format!("expected an array of numbers for {}", name))?;
+            memory.insert(name.to_string(), fill(name, values)?);
+        }
+    } else {
+        for (index, line) in string.lines().enumerate() {
+            let mut fields = line.split(',').map(|field| field.trim());
+            let name = match fields.next() {
+                Some("") | None => continue,
+                Some(name) => name,
+            };
+            let values = fields
+                .map(|field| if field.is_empty() { Ok(0.0) } else { field.parse::<f64>() })
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| format!("line {}: expected a list of numbers for {}", index + 1, name))?;
+            memory.insert(name.to_string(), fill(name, values)?);
+        }
+    }
+
+    Ok(memory)
+}
+
+// writes memory contents back out in the same format load_memory reads, leaving off trailing zeros
+pub fn dump_memory(memory: &HashMap<String, Vec<f64>>, json: bool) -> String {
+    let mut names: Vec<&String> = memory.keys().collect();
+    names.sort();
+
+    let mut lines = Vec::new();
+    for name in names.iter() {
+        let values = &memory[*name];
+        let length = values.iter().rposition(|value| *value != 0.0).map(|index| index + 1).unwrap_or(0);
+        // written exactly, since rounding like the game's printing does would lose packed colors and small fractions
+        let values: Vec<String> = values[..length].iter().map(|value| value.to_string()).collect();
+        if json {
+            lines.push(format!("    \"{}\": [{}]", name, values.join(", ")));
+        } else if values.is_empty() {
+            lines.push(name.to_string());
+        } else {
+            lines.push(format!("{},{}", name, values.join(",")));
+        }
+    }
+
+    if json {
+        format!("{{\n{}\n}}\n", lines.join(",\n"))
+    } else {
+        lines.push("".to_string());
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // whatever gets dumped should load back in exactly the same
+    fn round_trip(json: bool) {
+        let mut memory = HashMap::new();
+        let packed = f64::from_bits(0xff8800aa); // %ff8800aa
+        let mut values = vec![3.000001, -2.5, packed, 1e300, 0.1 + 0.2, f64::MIN_POSITIVE];
+        values.resize(64, 0.0);
+        memory.insert("cell1".to_string(), values);
+        memory.insert("bank2".to_string(), vec![0.0; 512]);
+
+        let extension = if json { "json" } else { "csv" };
+        let path = std::env::temp_dir().join(format!("mlogp-memory-{}.{}", std::process::id(), extension));
+        fs::write(&path, dump_memory(&memory, json)).unwrap();
+        let loaded = load_memory(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), memory.len());
+        for (name, values) in memory.iter() {
+            let bits: Vec<u64> = values.iter().map(|value| value.to_bits()).collect();
+            let loaded_bits: Vec<u64> = loaded[name].iter().map(|value| value.to_bits()).collect();
+            assert_eq!(bits, loaded_bits, "{} ({})", name, extension);
+        }
+    }
+
+    #[test]
+    fn dump_then_load_csv() {
+        round_trip(false);
+    }
+
+    #[test]
+    fn dump_then_load_json() {
+        round_trip(true);
+    }
+}
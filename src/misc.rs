@@ -7,3 +7,48 @@ pub fn error(message: &str, filename: &str, line: u32) {
 pub fn error_no_line(message: &str, filename: &str) {
     eprintln!("{} {} {}", format!("{}:", filename).bold(), "error:".red().bold(), message);
 }
+
+// for when mlogp itself got something wrong, rather than the program it's compiling
+pub fn internal_error(message: &str, filename: &str, line: u32) -> ! {
+    error(&format!("internal error: {}", message), filename, line);
+    eprintln!("{} this is a bug in mlogp, not your program. please report it along with the file that caused it", "note:".bold());
+    std::process::exit(1);
+}
+
+pub fn warning(message: &str, filename: &str, line: u32) {
+    eprintln!("{} {} {}", format!("{}:{}:", filename, line).bold(), "warning:".yellow().bold(), message);
+}
+
+pub fn warning_no_line(message: &str, filename: &str) {
+    eprintln!("{} {} {}", format!("{}:", filename).bold(), "warning:".yellow().bold(), message);
+}
+
+// splits a line of mlog into words, keeping strings in one piece and dropping comments
+pub fn split_line(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_string = false;
+    for c in line.chars() {
+        if in_string {
+            word.push(c);
+            if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            word.push(c);
+            in_string = true;
+        } else if c == '#' {
+            break;
+        } else if c.is_whitespace() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
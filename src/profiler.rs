@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use colored::*;
+
+use crate::Compiled;
+use crate::interpreter::Processor;
+
+// how many of the hottest lines get shown
+const HOTTEST_LINES: usize = 10;
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { count as f64 * 100.0 / total as f64 }
+}
+
+// prints out where a program spent its time, going by how many times each instruction ran
+pub fn print_profile(compiled: &Compiled, processor: &Processor, string: &str, processor_name: &str) {
+    let source: Vec<&str> = string.lines().collect();
+    let total = processor.executed;
+    let ticks = total.div_ceil(processor.instructions_per_tick) + processor.waited;
+
+    println!("{}", format!("profile ({}, {} instructions per tick)", processor_name, processor.instructions_per_tick).bold());
+    println!("total instructions: {}", total);
+    println!("estimated time: {} ticks ({:.2} seconds)", ticks, ticks as f64 / 60.0);
+
+    let mut lines: HashMap<u32, u64> = HashMap::new();
+    let mut routines: HashMap<Option<&str>, u64> = HashMap::new();
+    for (address, count) in processor.counts.iter().enumerate() {
+        *lines.entry(compiled.lines[address]).or_insert(0) += count;
+        *routines.entry(compiled.scopes[address].as_deref()).or_insert(0) += count;
+    }
+
+    let mut lines: Vec<(u32, u64)> = lines.into_iter().filter(|(_, count)| *count > 0).collect();
+    lines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    println!();
+    println!("{}", "hottest lines:".bold());
+    println!("{:>10} {:>7}  {:>5}  source", "count", "%", "line");
+    for (line, count) in lines.iter().take(HOTTEST_LINES) {
+        let text = source.get(*line as usize - 1).map(|text| text.trim()).unwrap_or("");
+        println!("{:>10} {:>6.2}%  {:>5}  {}", count, percent(*count, total), line, text);
+    }
+
+    let mut routines: Vec<(Option<&str>, u64)> = routines.into_iter().collect();
+    routines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    println!();
+    println!("{}", "routines:".bold());
+    println!("{:>10} {:>7}  routine", "count", "%");
+    for (routine, count) in routines.iter() {
+        println!("{:>10} {:>6.2}%  {}", count, percent(*count, total), routine.unwrap_or("(main program)"));
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::{HashMap, HashSet};
+
+use colored::*;
+
+use crate::misc::error;
+
+// the routines each routine calls, and the line each call is on, in the order the routines are declared
+pub type CallGraph = Vec<(String, Vec<(String, u32)>)>;
+
+// each routine keeps where to return to in one variable, so one that ends up calling itself through
+// other routines forgets where it was first called from
+pub fn check_recursion(calls: &CallGraph, filename: &str) {
+    if let Some((cycle, line)) = find_loop(calls) {
+        error(&format!("routines can't call each other in a loop: {}", cycle.join(" -> ")), filename, line);
+        println!("{} each call overwrites {}Return, so {} would forget where it was first called from. routines don't have a stack-based way to be called, so this has to be turned into a loop (or use an array as a stack)", "note:".bold(), cycle[0], cycle[0]);
+        std::process::exit(1);
+    }
+}
+
+// the first loop of calls, starting and ending with the same routine, and the line of the call that starts it
+fn find_loop(calls: &CallGraph) -> Option<(Vec<String>, u32)> {
+    let graph: HashMap<&str, &[(String, u32)]> = calls.iter().map(|(name, calls)| (name.as_str(), calls.as_slice())).collect();
+    let mut checked: HashSet<&str> = HashSet::new();
+    calls.iter().find_map(|(name, _)| find_cycle(&graph, name, &mut Vec::new(), &mut checked))
+}
+
+// follows every call out of a routine, with the path of calls that got there
+fn find_cycle<'a>(graph: &HashMap<&'a str, &'a [(String, u32)]>, name: &'a str, path: &mut Vec<(&'a str, u32)>, checked: &mut HashSet<&'a str>) -> Option<(Vec<String>, u32)> {
+    if checked.contains(name) {
+        return None;
+    }
+    let calls = *graph.get(name)?; // the gosub says it doesn't exist
+    for (callee, line) in calls.iter() {
+        if callee == name {
+            continue; // so does a routine calling itself
+        }
+        path.push((name, *line));
+        if let Some(start) = path.iter().position(|(caller, _)| caller == callee) {
+            let cycle = path[start..].iter().map(|(caller, _)| caller.to_string()).chain(std::iter::once(callee.to_string())).collect();
+            return Some((cycle, path[start].1));
+        }
+        if let Some(found) = find_cycle(graph, callee, path, checked) {
+            return Some(found);
+        }
+        path.pop();
+    }
+    checked.insert(name);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(routines: &[(&str, &[(&str, u32)])]) -> CallGraph {
+        routines.iter().map(|(name, calls)| (name.to_string(), calls.iter().map(|(callee, line)| (callee.to_string(), *line)).collect())).collect()
+    }
+
+    #[test]
+    fn finds_direct_loops() {
+        let calls = graph(&[("a", &[("b", 2)]), ("b", &[("a", 5)])]);
+        assert_eq!(find_loop(&calls), Some((vec!["a".to_string(), "b".to_string(), "a".to_string()], 2)));
+    }
+
+    #[test]
+    fn finds_longer_loops_from_where_they_start() {
+        let calls = graph(&[("main", &[("a", 1)]), ("a", &[("b", 4)]), ("b", &[("c", 7)]), ("c", &[("a", 10)])]);
+        assert_eq!(find_loop(&calls), Some((vec!["a".to_string(), "b".to_string(), "c".to_string(), "a".to_string()], 4)));
+    }
+
+    #[test]
+    fn ignores_what_gosub_reports() {
+        // calling itself and calling a routine that doesn't exist are both errors from gosub instead
+        let calls = graph(&[("a", &[("a", 2), ("nowhere", 3), ("b", 4)]), ("b", &[]), ("c", &[("b", 8), ("b", 9)])]);
+        assert_eq!(find_loop(&calls), None);
+    }
+}
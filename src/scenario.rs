@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::fs;
+
+use serde::Deserialize;
+
+use crate::interpreter::Object;
+
+// everything the processor can see of the world when running in the interpreter
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    pub links: Vec<Entity>, // buildings linked to the processor, in @links order
+    pub units: Vec<Entity>,
+    pub radar: HashMap<String, Vec<String>>, // names of whatever each turret's radar finds, one per call
+    pub uradar: Vec<String>,
+    pub ulocate: Vec<Located>,
+    pub blocks: Vec<BlockAt>, // for ucontrol getBlock
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entity {
+    pub name: String,
+    #[serde(default)]
+    pub sensors: HashMap<String, Sensed>,
+}
+
+// a sensable value, either the same the whole time or changing at certain ticks
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Sensed {
+    Constant(Scalar),
+    Timeline(Vec<Keyframe>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keyframe {
+    pub tick: u64,
+    pub value: Scalar,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Scalar {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Located {
+    pub found: bool,
+    pub x: f64,
+    pub y: f64,
+    pub building: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockAt {
+    pub x: i64,
+    pub y: i64,
+    pub block: String,
+    pub building: Option<String>,
+}
+
+// loads a scenario from a .json file, or toml otherwise
+pub fn load_scenario(filename: &str) -> Result<Scenario, String> {
+    let string = fs::read_to_string(filename).map_err(|err| format!("couldn't read file: {}", err))?;
+    if filename.ends_with(".json") {
+        serde_json::from_str(&string).map_err(|err| format!("invalid scenario: {}", err))
+    } else {
+        toml::from_str(&string).map_err(|err| format!("invalid scenario: {}", err))
+    }
+}
+
+impl Scenario {
+    pub fn is_link(&self, name: &str) -> bool {
+        self.links.iter().any(|link| link.name == name)
+    }
+
+    pub fn is_unit(&self, name: &str) -> bool {
+        self.units.iter().any(|unit| unit.name == name)
+    }
+
+    // turns a name from the scenario file into whatever it refers to
+    pub fn object(&self, name: &str) -> Object {
+        if let Some(content) = name.strip_prefix('@') {
+            Object::Content(content.to_string())
+        } else if name == "null" {
+            Object::Null
+        } else if self.is_link(name) {
+            Object::Building(name.to_string())
+        } else if self.is_unit(name) {
+            Object::Unit(name.to_string())
+        } else {
+            Object::String(name.to_string())
+        }
+    }
+
+    fn scalar(&self, scalar: &Scalar) -> Object {
+        match scalar {
+            Scalar::Bool(value) => Object::Number(if *value { 1.0 } else { 0.0 }),
+            Scalar::Number(value) => Object::Number(*value),
+            Scalar::Text(name) => self.object(name),
+        }
+    }
+
+    // what sensing a building or unit gives at a certain tick, null if the scenario doesn't say
+    pub fn sense(&self, target: &Object, property: &str, tick: u64) -> Object {
+        let entity = match target {
+            Object::Building(name) => self.links.iter().find(|link| &link.name == name),
+            Object::Unit(name) => self.units.iter().find(|unit| &unit.name == name),
+            _ => None,
+        };
+        let sensed = entity.and_then(|entity| entity.sensors.get(property.trim_start_matches('@')));
+        match sensed {
+            Some(Sensed::Constant(scalar)) => self.scalar(scalar),
+            Some(Sensed::Timeline(keyframes)) => {
+                keyframes.iter()
+                    .filter(|keyframe| keyframe.tick <= tick)
+                    .max_by_key(|keyframe| keyframe.tick)
+                    .map(|keyframe| self.scalar(&keyframe.value))
+                    .unwrap_or(Object::Null)
+            },
+            None => Object::Null,
+        }
+    }
+}
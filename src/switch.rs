@@ -0,0 +1,411 @@
+use std::rc::Rc;
+
+use logos::{Lexer, Logos};
+use colored::*;
+
+use crate::token::{number_error, Token};
+use crate::enums::Enums;
+use crate::misc::{error, warning};
+
+// switch x
+// case 0:
+//     ...
+// default:
+//     ...
+// endswitch
+//
+// dense integer cases turn into a jump table (adding to @counter), everything else into a chain of jumps.
+// each case jumps to the end when it's done, unless it ends in fallthrough
+
+// what a case compares against
+#[derive(PartialEq)]
+enum CaseValue {
+    Int(i64),
+    Other(String), // anything else jump can compare, like @copper or a variable
+}
+
+impl CaseValue {
+    fn text(&self) -> String {
+        match self {
+            CaseValue::Int(number) => number.to_string(),
+            CaseValue::Other(text) => text.to_string(),
+        }
+    }
+}
+
+// a case or the default, and the code after it
+struct Section {
+    value: Option<CaseValue>, // None for the default
+    text: String, // how the case was written, like State.Idle
+    fallthrough: bool,
+    address: u32, // filled in by the first pass
+}
+
+pub struct Switch {
+    sections: Vec<Section>,
+    pub end: u32, // filled in by the first pass
+}
+
+// a switch being compiled, and which of its sections the code is in
+pub struct Open {
+    switch: usize,
+    section: Option<usize>,
+}
+
+fn print_def() {
+    println!("{} switches are written as: switch value, then case value: or default: lines, each followed by code, then endswitch", "note:".bold());
+}
+
+// the bit between "case" and ":"
+fn parse_value(text: &str, enums: &Rc<Enums>, filename: &str, line: u32) -> CaseValue {
+    let mut lex = Token::lexer_with_extras(text, enums.clone());
+    let value = match lex.next() {
+        Some(Token::Int(number)) => CaseValue::Int(number),
+        Some(Token::Float(_)) | Some(Token::String(_)) | Some(Token::Name(_)) | Some(Token::SysVar(_)) | Some(Token::Bool(_)) => CaseValue::Other(lex.slice().to_string()),
+        Some(token) if matches!(token.clone().as_name(lex.slice()), Token::Name(_)) => CaseValue::Other(lex.slice().to_string()),
+        Some(Token::Error) if number_error(&lex).is_some() => { error(&number_error(&lex).unwrap(), filename, line); std::process::exit(1); },
+        _ => { error(&format!("expected a value to compare against, got {}", text), filename, line); print_def(); std::process::exit(1); },
+    };
+    if lex.next().is_some() {
+        error(&format!("case can only have one value, got {}", text), filename, line);
+        print_def();
+        std::process::exit(1);
+    }
+    value
+}
+
+// reads the value and colon after case, giving the value as it was written. 1: lexes as a label, anything else is followed by a colon
+pub fn case_text(lex: &mut Lexer<Token>, filename: &str, line: u32) -> String {
+    match lex.next() {
+        Some(Token::Label(text)) => return text,
+        Some(Token::Newline) | None => { error("expected a value to compare against after case", filename, line); print_def(); std::process::exit(1); },
+        Some(_) => (),
+    }
+    let text = lex.slice().to_string();
+    if lex.next() != Some(Token::Colon) {
+        error(&format!("expected : after case {}, got {}", text, lex.slice()), filename, line);
+        print_def();
+        std::process::exit(1);
+    }
+    text
+}
+
+// goes through every switch before compiling, since the code at the top of one depends on all its cases
+pub fn find_switches(string: &str, filename: &str, enums: &Rc<Enums>) -> Vec<Switch> {
+    let mut lex = Token::lexer_with_extras(string, enums.clone());
+    let mut line_count = 1;
+    let mut line_start = true;
+    let mut switches: Vec<Switch> = Vec::new();
+    let mut open: Vec<(usize, u32)> = Vec::new(); // switches that haven't ended yet, and where they started
+    let mut after_fallthrough = false;
+
+    while let Some(token) = lex.next() {
+        let at_start = line_start;
+        line_start = matches!(token, Token::Newline | Token::Label(_) | Token::Subroutine(_));
+        if !at_start || matches!(token, Token::Newline) {
+            if matches!(token, Token::Newline) {
+                line_count += 1;
+            }
+            continue;
+        }
+        if let Some(&(index, _)) = open.last() {
+            let starts_section = matches!(token, Token::Case | Token::Default | Token::EndSwitch);
+            if after_fallthrough && !starts_section {
+                error("fallthrough has to be the last thing in a case", filename, line_count);
+                std::process::exit(1);
+            }
+            if switches[index].sections.is_empty() && !starts_section {
+                error(&format!("expected case or default after switch, got {}", lex.slice()), filename, line_count);
+                print_def();
+                std::process::exit(1);
+            }
+        }
+        match token {
+            Token::Switch => {
+                switches.push(Switch { sections: Vec::new(), end: 0 });
+                open.push((switches.len() - 1, line_count));
+            },
+            Token::Case | Token::Default => {
+                let (index, _) = match open.last() {
+                    Some(open) => *open,
+                    None if token == Token::Default => continue, // just a label called default
+                    None => { error("case outside of a switch", filename, line_count); std::process::exit(1); },
+                };
+                let (value, text) = match token {
+                    Token::Case => {
+                        let text = case_text(&mut lex, filename, line_count);
+                        (Some(parse_value(&text, enums, filename, line_count)), text)
+                    },
+                    _ => (None, "default".to_string()),
+                };
+                let sections = &mut switches[index].sections;
+                if let Some(other) = sections.iter().find(|section| section.value == value) {
+                    match &value {
+                        Some(_) if other.text != text => error(&format!("case {} is the same as case {}, which is already in this switch", text, other.text), filename, line_count),
+                        Some(_) => error(&format!("case {} is in this switch more than once", text), filename, line_count),
+                        None => error("this switch already has a default", filename, line_count),
+                    }
+                    std::process::exit(1);
+                }
+                sections.push(Section { value, text, fallthrough: false, address: 0 });
+                after_fallthrough = false;
+                if !matches!(lex.clone().next(), Some(Token::Newline) | None) {
+                    error("the code for a case goes on the lines after it", filename, line_count);
+                    std::process::exit(1);
+                }
+            },
+            Token::Fallthrough => {
+                match open.last() {
+                    Some(&(index, _)) => switches[index].sections.last_mut().unwrap().fallthrough = true,
+                    None => { error("fallthrough outside of a switch", filename, line_count); std::process::exit(1); },
+                }
+                after_fallthrough = true;
+            },
+            Token::EndSwitch => {
+                let (index, start) = match open.pop() {
+                    Some(open) => open,
+                    None => { error("endswitch without a switch!", filename, line_count); std::process::exit(1); },
+                };
+                if after_fallthrough {
+                    error("there's nothing after the last case to fall through to", filename, line_count);
+                    std::process::exit(1);
+                }
+                if switches[index].sections.is_empty() {
+                    error("switch doesn't have any cases", filename, line_count);
+                    std::process::exit(1);
+                }
+                switches[index].check_enum(enums, filename, start);
+            },
+            _ => (),
+        }
+    }
+
+    if let Some(&(_, line)) = open.last() {
+        error("switch is missing an endswitch", filename, line);
+        std::process::exit(1);
+    }
+
+    switches
+}
+
+impl Switch {
+    // a switch over an enum should have a case for every member, or a default for the ones it leaves out
+    fn check_enum(&self, enums: &Enums, filename: &str, line: u32) {
+        if self.sections.iter().any(|section| section.value.is_none()) {
+            return;
+        }
+        let (name, members) = match self.sections.iter().find_map(|section| enums.enum_of(&section.text)) {
+            Some(found) => found,
+            None => return,
+        };
+        let missing: Vec<String> = members.iter()
+            .map(|member| format!("{}.{}", name, member))
+            .filter(|member| !self.sections.iter().any(|section| &section.text == member))
+            .collect();
+        if !missing.is_empty() {
+            warning(&format!("switch over enum {} doesn't have a case for {} (add a default: if that's on purpose)", name, missing.join(", ")), filename, line);
+        }
+    }
+
+    // the lowest and highest case, if they're close enough together for a jump table
+    fn table(&self) -> Option<(i64, i64)> {
+        let mut values = Vec::new();
+        for section in self.sections.iter() {
+            match section.value {
+                Some(CaseValue::Int(number)) => values.push(number),
+                Some(CaseValue::Other(_)) => return None,
+                None => (),
+            }
+        }
+        let (min, max) = (*values.iter().min()?, *values.iter().max()?);
+        let range = max - min + 1;
+        if values.len() >= 3 && range <= values.len() as i64 * 2 && range <= 256 {
+            Some((min, max))
+        } else {
+            None
+        }
+    }
+
+    fn default_address(&self) -> u32 {
+        self.sections.iter().find(|section| section.value.is_none()).map(|section| section.address).unwrap_or(self.end)
+    }
+
+    // how many instructions the code at the top takes
+    pub fn dispatch_count(&self) -> u32 {
+        match self.table() {
+            Some((min, max)) => 3 + if min != 0 { 1 } else { 0 } + (max - min + 1) as u32,
+            None => self.sections.iter().filter(|section| section.value.is_some()).count() as u32 + 1,
+        }
+    }
+
+    // the code at the top, which jumps to the right case
+    pub fn dispatch(&self, subject: &str, debug: bool) -> Vec<String> {
+        let comment = |what: String| if debug { format!(" # {}", what) } else { String::new() };
+        let default = self.default_address();
+        let default_comment = comment(if default == self.end { "endswitch".to_string() } else { "default".to_string() });
+        let mut code = Vec::new();
+        match self.table() {
+            Some((min, max)) => {
+                code.push(format!("jump {} lessThan {} {}{}", default, subject, min, default_comment));
+                code.push(format!("jump {} greaterThan {} {}{}", default, subject, max, default_comment));
+                let index = if min != 0 {
+                    code.push(format!("op sub switchIndex {} {}", subject, min));
+                    "switchIndex"
+                } else {
+                    subject
+                };
+                code.push(format!("op add @counter @counter {}", index));
+                for value in min..=max {
+                    match self.sections.iter().find(|section| section.value == Some(CaseValue::Int(value))) {
+                        Some(section) => code.push(format!("jump {} always{}", section.address, comment(format!("case {}", section.text)))),
+                        None => code.push(format!("jump {} always{}", default, default_comment)),
+                    }
+                }
+            },
+            None => {
+                for section in self.sections.iter() {
+                    if let Some(value) = &section.value {
+                        code.push(format!("jump {} equal {} {}{}", section.address, subject, value.text(), comment(format!("case {}", section.text))));
+                    }
+                }
+                code.push(format!("jump {} always{}", default, default_comment));
+            },
+        }
+        code
+    }
+}
+
+impl Open {
+    pub fn new(switch: usize) -> Open {
+        Open { switch, section: None }
+    }
+
+    // moves on to the next case, giving whether the one before it needs to jump to the end
+    pub fn next_section(&mut self, switches: &[Switch]) -> bool {
+        let previous = self.section;
+        self.section = Some(previous.map(|section| section + 1).unwrap_or(0));
+        previous.map(|section| !switches[self.switch].sections[section].fallthrough).unwrap_or(false)
+    }
+
+    pub fn switch(&self) -> usize {
+        self.switch
+    }
+
+    // where the current case's code starts
+    pub fn address(&self, switches: &[Switch]) -> u32 {
+        switches[self.switch].sections[self.section.unwrap()].address
+    }
+
+    pub fn set_address(&self, switches: &mut [Switch], address: u32) {
+        switches[self.switch].sections[self.section.unwrap()].address = address;
+    }
+}
+
+// skips whatever's left on the line, leaving the newline
+pub fn skip_line(lex: &mut Lexer<Token>) {
+    while !matches!(lex.clone().next(), Some(Token::Newline) | None) {
+        lex.next();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::find_enums;
+
+    fn switches(source: &str) -> Vec<Switch> {
+        find_switches(source, "test", &find_enums(source, "test"))
+    }
+
+    // what pass one would fill in, each section 10 apart starting at 100
+    fn place(switch: &mut Switch) {
+        for (index, section) in switch.sections.iter_mut().enumerate() {
+            section.address = 100 + index as u32 * 10;
+        }
+        switch.end = 100 + switch.sections.len() as u32 * 10;
+    }
+
+    #[test]
+    fn dense_cases_use_a_table() {
+        let mut switch = switches("switch x\ncase 1:\ncase 2:\ncase 4:\nendswitch\n").remove(0);
+        place(&mut switch);
+        assert_eq!(switch.table(), Some((1, 4)));
+        let code = switch.dispatch("x", false);
+        assert_eq!(code, vec![
+            "jump 130 lessThan x 1", "jump 130 greaterThan x 4", "op sub switchIndex x 1", "op add @counter @counter switchIndex",
+            "jump 100 always", "jump 110 always", "jump 130 always", "jump 120 always",
+        ]);
+        assert_eq!(code.len() as u32, switch.dispatch_count());
+
+        // starting at 0 doesn't need the subtraction
+        let switch = switches("switch x\ncase 0:\ncase 1:\ncase 2:\nendswitch\n").remove(0);
+        assert_eq!(switch.dispatch("x", false).len() as u32, switch.dispatch_count());
+        assert_eq!(switch.dispatch_count(), 6);
+    }
+
+    #[test]
+    fn sparse_cases_use_a_chain() {
+        let mut switch = switches("switch x\ncase 0:\ncase 100:\ncase 5:\ndefault:\nendswitch\n").remove(0);
+        place(&mut switch);
+        assert_eq!(switch.table(), None);
+        let code = switch.dispatch("x", false);
+        assert_eq!(code, vec!["jump 100 equal x 0", "jump 110 equal x 100", "jump 120 equal x 5", "jump 130 always"]);
+        assert_eq!(code.len() as u32, switch.dispatch_count());
+
+        // two cases are never worth a table
+        assert_eq!(switches("switch x\ncase 0:\ncase 1:\nendswitch\n")[0].table(), None);
+    }
+
+    #[test]
+    fn cases_dont_have_to_be_ints() {
+        let mut switch = switches("switch item\ncase @copper:\ncase \"a:b\":\ncase 1.5:\ncase other:\ncase 1:\ncase 2:\nendswitch\n").remove(0);
+        place(&mut switch);
+        assert_eq!(switch.table(), None);
+        assert_eq!(switch.dispatch("item", false), vec![
+            "jump 100 equal item @copper", "jump 110 equal item \"a:b\"", "jump 120 equal item 1.5", "jump 130 equal item other",
+            "jump 140 equal item 1", "jump 150 equal item 2", "jump 160 always",
+        ]);
+    }
+
+    #[test]
+    fn default_catches_the_rest() {
+        let mut switch = switches("switch x\ncase 0:\ndefault:\ncase 1:\ncase 2:\nendswitch\n").remove(0);
+        place(&mut switch);
+        assert_eq!(switch.default_address(), 110);
+        assert_eq!(switch.dispatch("x", true)[0], "jump 110 lessThan x 0 # default");
+
+        // without one, anything else skips the whole switch
+        let mut switch = switches("switch x\ncase 0:\ncase 1:\ncase 2:\nendswitch\n").remove(0);
+        place(&mut switch);
+        assert_eq!(switch.default_address(), switch.end);
+        assert_eq!(switch.dispatch("x", true)[0], "jump 130 lessThan x 0 # endswitch");
+    }
+
+    #[test]
+    fn fallthrough_skips_the_jump_to_the_end() {
+        let switches = switches("switch x\ncase 0:\n    print \"a\"\n    fallthrough\ncase 1:\n    print \"b\"\ndefault:\nendswitch\n");
+        let fallthroughs: Vec<bool> = switches[0].sections.iter().map(|section| section.fallthrough).collect();
+        assert_eq!(fallthroughs, vec![true, false, false]);
+
+        // the first case has nothing before it, then case 0 falls into case 1, which jumps out before default
+        let mut open = Open::new(0);
+        assert!(!open.next_section(&switches));
+        assert!(!open.next_section(&switches));
+        assert!(open.next_section(&switches));
+    }
+
+    #[test]
+    fn nested_switches_are_separate() {
+        let switches = switches("switch x\ncase 0:\n    switch y\n    case \"a\":\n    case \"b\":\n    endswitch\ncase 1:\nendswitch\nswitch z\ndefault:\nendswitch\n");
+        let texts: Vec<Vec<&str>> = switches.iter().map(|switch| switch.sections.iter().map(|section| section.text.as_str()).collect()).collect();
+        assert_eq!(texts, vec![vec!["0", "1"], vec!["\"a\"", "\"b\""], vec!["default"]]);
+    }
+
+    #[test]
+    fn enum_cases_are_ints() {
+        let switch = switches("enum State { Idle, Mining, Returning }\nswitch state\ncase State.Idle:\ncase State.Mining:\ncase State.Returning:\nendswitch\n").remove(0);
+        assert_eq!(switch.table(), Some((0, 2)));
+        let texts: Vec<&str> = switch.sections.iter().map(|section| section.text.as_str()).collect();
+        assert_eq!(texts, vec!["State.Idle", "State.Mining", "State.Returning"]);
+    }
+}
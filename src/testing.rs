@@ -0,0 +1,174 @@
+use std::collections::{HashMap, HashSet};
+
+use logos::Logos;
+use colored::*;
+
+use crate::token::Token;
+use crate::enums::skip_declaration;
+use crate::misc::error;
+
+// a test "name" ... endtest block
+pub struct TestCase {
+    pub name: String,
+    pub start: u32, // line the test starts on
+    pub end: u32, // line of the endtest
+    calls: Vec<String>,
+}
+
+struct RoutineSpan {
+    start: u32,
+    end: u32,
+    calls: Vec<String>,
+}
+
+pub struct TestSuite {
+    pub tests: Vec<TestCase>,
+    routines: HashMap<String, RoutineSpan>,
+    enums: Vec<(u32, u32)>, // lines enums are declared on, which every test keeps
+}
+
+enum Scope {
+    Main,
+    Routine(String),
+    Test(usize),
+}
+
+// finds all the tests in a file, along with which routines everything calls
+pub fn find_tests(string: &str, filename: &str) -> TestSuite {
+    let mut lex = Token::lexer(string);
+    let mut line_count = 1;
+    let mut suite = TestSuite { tests: Vec::new(), routines: HashMap::new(), enums: Vec::new() };
+    let mut scope = Scope::Main;
+
+    fn close_routine(suite: &mut TestSuite, scope: &mut Scope, line: u32) {
+        if let Scope::Routine(name) = scope {
+            if let Some(routine) = suite.routines.get_mut(name) {
+                routine.end = line;
+            }
+            *scope = Scope::Main;
+        }
+    }
+
+    let mut line_start = true;
+    while let Some(token) = lex.next() {
+        // keywords only count at the start of a line, anywhere else they're names
+        let token = if line_start { token } else { token.as_name(lex.slice()) };
+        line_start = matches!(token, Token::Newline | Token::Label(_) | Token::Subroutine(_) | Token::Default);
+        match token {
+            Token::Newline => line_count += 1,
+            Token::Subroutine(name) => {
+                if let Scope::Test(_) = scope {
+                    error("can't declare a subroutine inside a test!", filename, line_count);
+                    std::process::exit(1);
+                }
+                close_routine(&mut suite, &mut scope, line_count - 1);
+                suite.routines.insert(name.to_string(), RoutineSpan { start: line_count, end: u32::MAX, calls: Vec::new() });
+                scope = Scope::Routine(name);
+            },
+            Token::InstEndRoutine => close_routine(&mut suite, &mut scope, line_count),
+            Token::Enum => {
+                let start = line_count;
+                line_count += skip_declaration(&mut lex);
+                suite.enums.push((start, line_count));
+            },
+            Token::Test => {
+                match scope {
+                    Scope::Test(_) => { error("can't start a test inside another test!", filename, line_count); std::process::exit(1); },
+                    Scope::Routine(_) => close_routine(&mut suite, &mut scope, line_count - 1),
+                    Scope::Main => (),
+                }
+                let name = match lex.next() {
+                    Some(Token::String(name)) => name.trim_matches('"').to_string(),
+                    _ => {
+                        error(&format!("expected test name, got {}", lex.slice()), filename, line_count);
+                        println!("{} tests are defined as: test \"name\" ... endtest", "note:".bold());
+                        std::process::exit(1);
+                    },
+                };
+                suite.tests.push(TestCase { name, start: line_count, end: 0, calls: Vec::new() });
+                scope = Scope::Test(suite.tests.len() - 1);
+            },
+            Token::EndTest => {
+                if let Scope::Test(index) = scope {
+                    suite.tests[index].end = line_count;
+                    scope = Scope::Main;
+                } else {
+                    error("endtest without a test!", filename, line_count);
+                    std::process::exit(1);
+                }
+            },
+            Token::InstAssert if !matches!(scope, Scope::Test(_)) => {
+                error("can't assert outside of a test!", filename, line_count);
+                std::process::exit(1);
+            },
+            Token::InstGosub | Token::InstGosubCond => {
+                if let Some(Token::Name(name)) = lex.next().map(|token| token.as_name(lex.slice())) {
+                    match &scope {
+                        Scope::Routine(routine) => suite.routines.get_mut(routine).unwrap().calls.push(name),
+                        Scope::Test(index) => suite.tests[*index].calls.push(name),
+                        Scope::Main => (),
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+
+    if let Scope::Test(index) = scope {
+        error(&format!("test \"{}\" is missing an endtest", suite.tests[index].name), filename, suite.tests[index].start);
+        std::process::exit(1);
+    }
+
+    suite
+}
+
+impl TestSuite {
+    // blanks out every line that doesn't pass the check, keeping the line numbers the same
+    fn filter_lines(string: &str, keep: impl Fn(u32, &str) -> Option<String>) -> String {
+        string.split('\n').enumerate()
+            .map(|(index, line)| keep(index as u32 + 1, line).unwrap_or_default())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // the source with all the tests taken out, for compiling normally
+    pub fn strip(&self, string: &str) -> String {
+        if self.tests.is_empty() {
+            return string.to_string();
+        }
+        TestSuite::filter_lines(string, |line, text| {
+            if self.tests.iter().any(|test| line >= test.start && line <= test.end) {
+                None
+            } else {
+                Some(text.to_string())
+            }
+        })
+    }
+
+    // the source with just one test and the routines it ends up calling, the test stops at its endtest
+    pub fn select(&self, string: &str, index: usize) -> String {
+        let test = &self.tests[index];
+
+        let mut included: HashSet<&str> = HashSet::new();
+        let mut to_visit: Vec<&str> = test.calls.iter().map(|name| name.as_str()).collect();
+        while let Some(name) = to_visit.pop() {
+            if let Some(routine) = self.routines.get(name) {
+                if included.insert(name) {
+                    to_visit.extend(routine.calls.iter().map(|name| name.as_str()));
+                }
+            }
+        }
+
+        TestSuite::filter_lines(string, |line, text| {
+            if line == test.end {
+                Some("end".to_string())
+            } else if (line > test.start && line < test.end)
+                || included.iter().any(|name| line >= self.routines[*name].start && line <= self.routines[*name].end)
+                || self.enums.iter().any(|&(start, end)| line >= start && line <= end) {
+                Some(text.to_string())
+            } else {
+                None
+            }
+        })
+    }
+}
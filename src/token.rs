@@ -1,21 +1,96 @@
-use logos::Logos;
+use std::rc::Rc;
+
+use logos::{Filter, Lexer, Logos, Skip};
+
+use crate::enums::Enums;
+
+// whether the token is stuck onto a name or number before it, like the -1 in x-1
+fn follows_word(lex: &Lexer<Token>) -> bool {
+    lex.source()[..lex.span().start].chars().next_back().is_some_and(|c| c.is_alphanumeric() || "_.@\"]}".contains(c))
+}
+
+// a minus sign only makes a number negative when it isn't stuck onto something, x-1 is a mistake rather than x followed by -1
+fn negative_allowed(lex: &Lexer<Token>) -> bool {
+    !lex.slice().starts_with('-') || !follows_word(lex)
+}
+
+fn parse_int(lex: &mut Lexer<Token>) -> Option<i64> {
+    if !negative_allowed(lex) {
+        return None;
+    }
+    lex.slice().parse().ok()
+}
+
+// 1e400 would come out as inf, which the game doesn't understand
+fn parse_float(lex: &mut Lexer<Token>) -> Option<f64> {
+    if !negative_allowed(lex) {
+        return None;
+    }
+    lex.slice().parse().ok().filter(|value: &f64| value.is_finite())
+}
+
+// "-0x1f" -> -31, the prefix is skipped over whatever it is
+fn parse_radix(lex: &mut Lexer<Token>, radix: u32) -> Option<i64> {
+    if !negative_allowed(lex) {
+        return None;
+    }
+    let slice = lex.slice();
+    let (negative, digits) = match slice.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, slice),
+    };
+    let value = i64::from_str_radix(&digits[2..], radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+// "#ff8800" -> [255, 136, 0, 255] (no alpha means fully opaque), unless there's more stuck on the end (a comment like "#ff8800ish"),
+// or it's where an instruction goes (a comment like "#c0ffee" on its own line), since colors are only ever arguments
+fn parse_color(lex: &mut Lexer<Token>) -> Filter<[u8; 4]> {
+    let before = lex.source()[..lex.span().start].rsplit('\n').next().unwrap_or("").trim();
+    if lex.remainder().starts_with(|c: char| !c.is_whitespace()) || before.is_empty() || before.ends_with(':') {
+        skip_comment(lex);
+        return Filter::Skip;
+    }
+    let digits = &lex.slice()[1..];
+    let mut color = [255; 4];
+    for (channel, index) in color.iter_mut().zip((0..digits.len()).step_by(2)) {
+        *channel = u8::from_str_radix(&digits[index..index + 2], 16).unwrap();
+    }
+    Filter::Emit(color)
+}
+
+// comments go to the end of the line
+fn skip_comment(lex: &mut Lexer<Token>) -> Skip {
+    let length = lex.remainder().find('\n').unwrap_or(lex.remainder().len());
+    lex.bump(length);
+    Skip
+}
 
 // tokens - basically lil data structures the entire program is split up into
 #[derive(Logos, Debug, PartialEq, Clone)]
+#[logos(extras = Rc<Enums>)]
 pub enum Token {
     // boolean value (true/false)
     #[token("true", |_| true)]
     #[token("false", |_| false)]
     Bool(bool),
 
-    // floating point number (with decimal point)
-    #[regex("[0-9]+\\.[0-9]+", |lex| lex.slice().parse())]
+    // floating point number (with decimal point, or in scientific notation like 1e-3), maybe negative
+    #[regex("-?[0-9]+\\.[0-9]+", parse_float)]
+    #[regex("-?[0-9]+(\\.[0-9]+)?[eE][+-]?[0-9]+", parse_float, priority = 3)]
     Float(f64),
 
-    // integer number (no decimal point)
-    #[regex("[0-9]+", |lex| lex.slice().parse(), priority = 2)]
+    // integer number (no decimal point), in decimal, hex (0xff) or binary (0b1010), maybe negative. enum members (State.Idle) are numbers too
+    #[regex("-?[0-9]+", parse_int, priority = 3)]
+    #[regex("-?0x[0-9a-fA-F]+", |lex| parse_radix(lex, 16), priority = 3)]
+    #[regex("-?0b[01]+", |lex| parse_radix(lex, 2), priority = 3)]
+    #[regex("[a-zA-Z_][a-zA-Z0-9_]*\\.[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.extras.value(lex.slice()))]
     Int(i64),
 
+    // color (#rrggbb or #rrggbbaa), which is kept as an array since logos clones whatever token it lexed last
+    #[regex("#[0-9a-fA-F][0-9a-fA-F][0-9a-fA-F][0-9a-fA-F][0-9a-fA-F][0-9a-fA-F]([0-9a-fA-F][0-9a-fA-F])?", parse_color)]
+    Color([u8; 4]),
+
     // string
     #[regex("\"(\\.|[^\"])*\"", |lex| lex.slice().parse())] // we don't need to bother with de-escaping string contents n shit, it'll just be spat back out anyway
     String(String),
@@ -29,6 +104,7 @@ pub enum Token {
     #[token("mod", |lex| lex.slice().parse())]
     #[token("pow", |lex| lex.slice().parse())]
     #[token("equal", |lex| lex.slice().parse())]
+    #[token("notEqual", |lex| lex.slice().parse())]
     #[token("strictEqual", |lex| lex.slice().parse())]
     #[token("not", |lex| lex.slice().parse())]
     #[token("land", |lex| lex.slice().parse())]
@@ -59,168 +135,38 @@ pub enum Token {
     #[token("rand", |lex| lex.slice().parse())]
     Op(String),
 
-    // all the instructions
-    #[token("write")]
-    InstWrite,
-
-    #[token("read")]
-    InstRead,
-
-    #[token("draw")]
-    InstDraw,
-
-    #[token("clear")]
-    SubInstClear,
-
-    #[token("color")]
-    SubInstColor,
-    
-    #[token("stroke")]
-    SubInstStroke,
-    
-    #[token("line")]
-    SubInstLine,
-    
-    #[token("rect")]
-    SubInstRect,
-    
-    #[token("lineRect")]
-    SubInstLineRect,
-    
-    #[token("poly")]
-    SubInstPoly,
-    
-    #[token("linePoly")]
-    SubInstLinePoly,
-    
-    #[token("triangle")]
-    SubInstTriangle,
-    
-    #[token("image")]
-    SubInstImage,
-
-    #[token("drawflush")]
-    InstDrawFlush,
-
-    #[token("print")]
-    InstPrint,
-
-    #[token("printflush")]
-    InstPrintFlush,
-
-    #[token("getlink")]
-    InstGetLink,
-
-    #[token("control")]
-    InstControl,
-
-    #[token("enabled")]
-    SubInstEnabled,
-    
-    #[token("shoot")]
-    SubInstShoot,
-    
-    #[token("shootp")]
-    SubInstShootP,
-    
-    #[token("configure")]
-    SubInstConfigure,
-
-    #[token("radar")]
-    InstRadar,
-
-    #[token("sensor")]
-    InstSensor,
-
-    #[token("set")]
-    InstSet,
-
+    // keywords for what the compiler takes care of itself. every other instruction and sub-instruction
+    // is looked up by name in the instruction set, so that's the one place they're spelled out
     #[token("op")]
     InstOp,
 
-    #[token("end")]
-    InstEnd,
-
-    #[token("jump")]
-    InstJump,
-
-    #[token("ubind")]
-    InstUnitBind,
-
-    #[token("ucontrol")]
-    InstUnitControl,
-
-    #[token("idle")]
-    SubInstIdle,
-
-    #[token("stop")]
-    SubInstStop,
-
-    #[token("move")]
-    SubInstMove,
-
-    #[token("approach")]
-    SubInstApproach,
-
-    #[token("boost")]
-    SubInstBoost,
+    #[token("array")]
+    Array,
 
-    #[token("pathfind")]
-    SubInstPathFind,
+    #[token("regs")]
+    Regs,
 
-    #[token("target")]
-    SubInstTarget,
+    #[token("enum")]
+    Enum,
 
-    #[token("targetp")]
-    SubInstTargetP,
+    #[token("switch")]
+    Switch,
 
-    #[token("itemDrop")]
-    SubInstItemDrop,
+    #[token("case")]
+    Case,
 
-    #[token("itemTake")]
-    SubInstItemTake,
+    // after a case's value
+    #[token(":")]
+    Colon,
 
-    #[token("payDrop")]
-    SubInstPayDrop,
+    #[token("default:")]
+    Default,
 
-    #[token("PayTake")]
-    SubInstPayTake,
+    #[token("fallthrough")]
+    Fallthrough,
 
-    #[token("Mine")]
-    SubInstMine,
-
-    #[token("flag")]
-    SubInstFlag,
-
-    #[token("build")]
-    SubInstBuild,
-
-    #[token("getBlock")]
-    SubInstGetBlock,
-
-    #[token("within")]
-    SubInstWithin,
-
-    #[token("uradar")]
-    InstUnitRadar,
-
-    #[token("ulocate")]
-    InstUnitLocate,
-
-    #[token("ore")]
-    SubInstOre,
-
-    #[token("building")]
-    SubInstBuilding,
-
-    #[token("spawn")]
-    SubInstSpawn,
-
-    #[token("noop")]
-    InstNoOp,
-
-    #[regex("goto")]
-    InstGoto,
+    #[token("endswitch")]
+    EndSwitch,
 
     #[regex("gosub")]
     InstGosub,
@@ -234,12 +180,47 @@ pub enum Token {
     #[token("endroutine")]
     InstEndRoutine,
 
-    #[regex("[a-zA-Z0-9]+", |lex| lex.slice().parse())]
+    #[token("test")]
+    Test,
+
+    #[token("endtest")]
+    EndTest,
+
+    #[token("assert")]
+    InstAssert,
+
+    #[regex("[a-zA-Z0-9_]+", |lex| lex.slice().parse())]
     Name(String),
 
     #[token("always")]
     Always,
 
+    // for arrays, buf[i] = x and x = buf[i + 1]
+    #[token("[")]
+    LeftBracket,
+
+    #[token("]")]
+    RightBracket,
+
+    #[token("=")]
+    Assign,
+
+    #[token("+")]
+    Plus,
+
+    #[token("-")]
+    Minus,
+
+    // for enums, enum State { Idle, Mining }
+    #[token("{")]
+    LeftBrace,
+
+    #[token("}")]
+    RightBrace,
+
+    #[token(",")]
+    Comma,
+
     #[regex("\n")]
     Newline,
 
@@ -255,11 +236,40 @@ pub enum Token {
     })]
     Subroutine(String),
 
-    #[regex("@[a-zA-Z]+", |lex| lex.slice().parse())]
+    #[regex("@[a-zA-Z][a-zA-Z0-9-]*", |lex| lex.slice().parse())]
     SysVar(String),
 
     #[error]
     #[regex(r"[ \t\f]+", logos::skip)]
-    #[regex(r"#.*", logos::skip)]
+    #[token("#", skip_comment)]
     Error,
 }
+
+// names the language itself uses for values, which can't be used for anything else
+pub const BUILT_IN_VALUES: [&str; 3] = ["true", "false", "null"];
+
+// what's wrong with a number the lexer turned down, if the error token is one
+pub fn number_error(lex: &Lexer<Token>) -> Option<String> {
+    let slice = lex.slice();
+    if !slice.trim_start_matches('-').starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    if !negative_allowed(lex) {
+        Some(format!("{} is stuck onto what's before it, so it isn't a negative number (put a space before the -)", slice))
+    } else {
+        Some(format!("{} is too big to be a number", slice))
+    }
+}
+
+impl Token {
+    // keywords only mean something where an instruction, sub-instruction or op goes, anywhere else they're plain names
+    pub fn as_name(self, slice: &str) -> Token {
+        match self {
+            Token::Bool(_) | Token::Float(_) | Token::Int(_) | Token::Color(_) | Token::String(_) | Token::Name(_) | Token::Newline
+                | Token::Label(_) | Token::Subroutine(_) | Token::SysVar(_) | Token::Error
+                | Token::LeftBracket | Token::RightBracket | Token::Assign | Token::Plus | Token::Minus
+                | Token::LeftBrace | Token::RightBrace | Token::Comma | Token::Colon => self,
+            _ => Token::Name(slice.to_string()),
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use logos::Lexer;
 
-use crate::token::Token;
+use std::fmt::Formatter;
+use std::fmt::Display;
+
+use crate::token::{number_error, Token, BUILT_IN_VALUES};
 use crate::misc::{error, error_no_line};
+use crate::content::database;
 
 #[derive(Debug, PartialEq)]
 pub enum Value {
@@ -17,17 +21,23 @@ pub enum Value {
 // will turn a value for a command from tokens back into text, double checking the arguments
 impl Value {
     pub fn compile(&self, lex: &mut Lexer<Token>, variables: &[String], allow_none: bool, filename: &str, line: u32) -> Option<String> {
-        let next = lex.next();
+        let next = lex.next().map(|token| token.as_name(lex.slice()));
+        if let Value::Variable(_) = self {
+            if next.is_some() && BUILT_IN_VALUES.contains(&lex.slice()) {
+                error(&format!("{} is a built in value, so it can't be used as a variable name", lex.slice()), filename, line);
+                return None;
+            }
+        }
         match &next {
             None => {
                 if !allow_none {
-                    error_no_line(&format!("expected {} or variable, got EOF", self.to_string()), filename)
+                    error_no_line(&format!("expected {} or variable, got EOF", self), filename)
                 }
                 None
             },
             Some(Token::Newline) => {
                 if !allow_none {
-                    error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self.to_string(), lex.slice()), filename, line)
+                    error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self, lex.slice()), filename, line)
                 }
                 None
             },
@@ -35,7 +45,7 @@ impl Value {
                 match self {
                     Value::Bool(_) | Value::Any(_) => Some(val.to_string()),
                     _ => {
-                        error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self.to_string(), lex.slice()), filename, line);
+                        error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self, lex.slice()), filename, line);
                         None
                     },
                 }
@@ -43,17 +53,19 @@ impl Value {
             Some(Token::Float(val)) => {
                 match self {
                     Value::Float(_) | Value::Any(_) => Some(val.to_string()),
+                    Value::Int(_) if val.fract() == 0.0 => Some(val.to_string()), // like 1e3
                     _ => {
-                        error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self.to_string(), lex.slice()), filename, line);
+                        error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self, lex.slice()), filename, line);
                         None
                     },
                 }
             },
             Some(Token::Int(val)) => {
                 match self {
-                    Value::Int(_) | Value::Any(_) => Some(val.to_string()),
+                    Value::Int(_) | Value::Float(_) | Value::Any(_) => Some(val.to_string()), // any int is a perfectly good float
+                    Value::Bool(_) if *val == 0 || *val == 1 => Some(val.to_string()), // the game writes bools out as numbers
                     _ => {
-                        error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self.to_string(), lex.slice()), filename, line);
+                        error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self, lex.slice()), filename, line);
                         None
                     },
                 }
@@ -62,7 +74,7 @@ impl Value {
                 match self {
                     Value::String(_) | Value::Any(_) => Some(val.to_string()),
                     _ => {
-                        error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self.to_string(), lex.slice()), filename, line);
+                        error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self, lex.slice()), filename, line);
                         None
                     },
                 }
@@ -72,22 +84,50 @@ impl Value {
                     Some(val.to_string())
                 } else {
                     match self {
-                        Value::Name(_, _) | Value::Any(_) => Some(val.to_string()),
+                        Value::Name(_, category) => self.check_name(category, val, filename, line),
+                        Value::Any(_) => Some(val.to_string()),
                         _ => {
-                            error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self.to_string(), lex.slice()), filename, line);
+                            error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self, lex.slice()), filename, line);
                             None
                         },
                     }
                 }
             },
-            Some(Token::SysVar(val)) => Some(val.to_string()),
+            Some(Token::SysVar(val)) => {
+                match self {
+                    Value::Name(_, category) => self.check_name(category, val, filename, line),
+                    _ => Some(val.to_string()),
+                }
+            },
+            Some(Token::Error) if number_error(lex).is_some() => {
+                error(&number_error(lex).unwrap(), filename, line);
+                None
+            },
             _ => {
-                error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self.to_string(), lex.slice()), filename, line);
+                error(&format!("expected {} or variable, got {} (maybe you forgot to create a variable?)", self, lex.slice()), filename, line);
+                None
+            },
+        }
+    }
+
+    // makes sure a name is one the game knows for the category, like an item for an Item
+    fn check_name(&self, category: &str, name: &str, filename: &str, line: u32) -> Option<String> {
+        match database().check(category, name) {
+            Ok(()) => Some(name.to_string()),
+            Err(closest) => {
+                let suggestion = closest.map(|closest| format!(", did you mean {}?", closest)).unwrap_or_default();
+                error(&format!("{} isn't a known {}{}", name, category, suggestion), filename, line);
                 None
             },
         }
     }
 
+    pub fn name(&self) -> &str {
+        match self {
+            Value::Bool(name) | Value::Float(name) | Value::Int(name) | Value::String(name) | Value::Name(name, _) | Value::Variable(name) | Value::Any(name) => name,
+        }
+    }
+
     pub fn to_string_with_name(&self) -> String {
         match self {
             Value::Bool(name) => format!("{} (bool)", name),
@@ -101,16 +141,41 @@ impl Value {
     }
 }
 
-impl ToString for Value {
-    fn to_string(&self) -> String {
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Value::Bool(_) => "bool".to_string(),
-            Value::Float(_) => "float".to_string(),
-            Value::Int(_) => "int".to_string(),
-            Value::String(_) => "string".to_string(),
-            Value::Name(_, name) => format!("name ({})", name),
-            Value::Variable(_) => "variable".to_string(),
-            Value::Any(_) => "any".to_string(),
+            Value::Bool(_) => write!(f, "bool"),
+            Value::Float(_) => write!(f, "float"),
+            Value::Int(_) => write!(f, "int"),
+            Value::String(_) => write!(f, "string"),
+            Value::Name(_, name) => write!(f, "name ({})", name),
+            Value::Variable(_) => write!(f, "variable"),
+            Value::Any(_) => write!(f, "any"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logos::Logos;
+
+    fn compile(value: Value, source: &str) -> Option<String> {
+        value.compile(&mut Token::lexer(source), &[], false, "test", 1)
+    }
+
+    #[test]
+    fn ints_are_floats() {
+        // wait 1, ucontrol move 10 20 and the like, nobody writes 1.0
+        assert_eq!(compile(Value::Float("seconds".to_string()), "1"), Some("1".to_string()));
+        assert_eq!(compile(Value::Float("x".to_string()), "-20"), Some("-20".to_string()));
+    }
+
+    #[test]
+    fn whole_floats_are_ints() {
+        // scientific notation always lexes as a float, even when it's a whole number
+        assert_eq!(compile(Value::Int("address".to_string()), "1e3"), Some("1000".to_string()));
+        assert_eq!(compile(Value::Int("address".to_string()), "2.5e1"), Some("25".to_string()));
+        assert_eq!(compile(Value::Int("address".to_string()), "1.5"), None);
+    }
+}
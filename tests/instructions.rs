@@ -0,0 +1,217 @@
+// compiles every instruction and sub-instruction in the bundled instruction set, on every version of
+// the game, to make sure the names and arguments it's written with are the ones that come out.
+// also checks the arguments the compiler fills in itself, like colors and array accesses
+
+use std::fs;
+use std::process::{Command, Output};
+
+use toml::{Table, Value};
+
+const VERSIONS: [u32; 3] = [6, 7, 8];
+
+fn table(source: &str) -> Table {
+    source.parse().unwrap()
+}
+
+// runs mlogp on some source code, with whatever flags
+fn compile(name: &str, source: &str, flags: &[&str]) -> Output {
+    let path = std::env::temp_dir().join(format!("mlogp-{}-{}.mlogp", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_mlogp"))
+        .arg("-i").arg(&path)
+        .args(flags)
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+fn version(field: Option<&Value>) -> Option<u32> {
+    field.map(|version| version.as_str().unwrap().trim_start_matches('v').parse().unwrap())
+}
+
+fn available(definition: &Table, target: u32, world: bool) -> bool {
+    let world_only = definition.get("world").and_then(|world| world.as_bool()).unwrap_or(false);
+    version(definition.get("since")).map(|since| target >= since).unwrap_or(true)
+        && version(definition.get("until")).map(|until| target <= until).unwrap_or(true)
+        && (world || !world_only)
+}
+
+// a name the compiler will take for a category, going by content.toml
+fn example_name(content: &Table, category: &str) -> String {
+    match content.get(category).and_then(|names| names.as_array()) {
+        Some(names) => {
+            let first = names[0].as_str().unwrap();
+            if content.contains_key(first) { example_name(content, first) } else { first.to_string() }
+        },
+        None => "block1".to_string(), // categories it doesn't list take anything
+    }
+}
+
+// something that fits an argument, written the way the compiler writes it back out
+fn example_argument(content: &Table, argument: &str) -> String {
+    let argument = argument.split('=').next().unwrap(); // defaults get written out anyway
+    let (name, kind) = argument.split_once(':').unwrap();
+    match kind.trim() {
+        "bool" => "true".to_string(),
+        "float" => "1.5".to_string(),
+        "int" => "2".to_string(),
+        "string" => "\"text\"".to_string(),
+        "any" => "x".to_string(),
+        "output" => format!("out_{}", name.trim()),
+        category => example_name(content, category),
+    }
+}
+
+fn example_line(content: &Table, names: &[&str], definition: &Table) -> String {
+    let mut words: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+    for argument in definition.get("arguments").and_then(|arguments| arguments.as_array()).into_iter().flatten() {
+        words.push(example_argument(content, argument.as_str().unwrap()));
+    }
+    words.join(" ")
+}
+
+// every instruction the target has as a line of source, and every one it doesn't have
+fn example_lines(target: u32, world: bool) -> (Vec<String>, Vec<String>) {
+    let isa = table(include_str!("../src/isa.toml"));
+    let content = table(include_str!("../src/content.toml"));
+    let (mut lines, mut missing) = (Vec::new(), Vec::new());
+    for instruction in isa["instruction"].as_array().unwrap() {
+        let instruction = instruction.as_table().unwrap();
+        let name = instruction["name"].as_str().unwrap();
+        let subs = instruction.get("sub").and_then(|subs| subs.as_array());
+        let lines_for = |definition: &Table, names: &[&str], lines: &mut Vec<String>, missing: &mut Vec<String>| {
+            if available(definition, target, world) {
+                lines.push(example_line(&content, names, definition));
+            } else {
+                missing.push(example_line(&content, names, definition));
+            }
+        };
+        match subs {
+            Some(subs) if available(instruction, target, world) => {
+                for sub in subs {
+                    let sub = sub.as_table().unwrap();
+                    lines_for(sub, &[name, sub["name"].as_str().unwrap()], &mut lines, &mut missing);
+                }
+            },
+            Some(_) => missing.push(name.to_string()),
+            None => lines_for(instruction, &[name], &mut lines, &mut missing),
+        }
+    }
+    (lines, missing)
+}
+
+#[test]
+fn every_instruction_compiles_unchanged() {
+    for target in VERSIONS {
+        for world in [false, true] {
+            let (lines, _) = example_lines(target, world);
+            let version = format!("v{}", target);
+            let mut flags = vec!["-t", &version];
+            if world {
+                flags.push("-w");
+            }
+            let output = compile("every", &(lines.join("\n") + "\n"), &flags);
+            assert!(output.status.success(), "{:?}: {}", flags, String::from_utf8_lossy(&output.stderr));
+            let compiled: Vec<String> = String::from_utf8(output.stdout).unwrap().lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| line.to_string())
+                .collect();
+            assert_eq!(lines, compiled, "{:?}", flags);
+        }
+    }
+}
+
+#[test]
+fn missing_instructions_say_why() {
+    for target in VERSIONS {
+        let (_, missing) = example_lines(target, false);
+        let version = format!("v{}", target);
+        for line in missing {
+            let output = compile("missing", &(line.clone() + "\n"), &["-t", &version]);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(!output.status.success(), "{} compiled on {}", line, version);
+            assert!(stderr.contains("isn't available on") || stderr.contains("only works in world processors"), "{} on {}: {}", line, version, stderr);
+        }
+    }
+}
+
+#[test]
+fn sub_instructions_are_case_sensitive() {
+    let output = compile("case", "ucontrol PayTake true\n", &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("did you mean payTake?"));
+
+    let output = compile("case", "ucontrol payTake true\nucontrol mine 1 2\n", &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "ucontrol payTake true\nucontrol mine 1 2");
+}
+
+#[test]
+fn colors_fill_draw_color() {
+    let output = compile("color", "draw color #ff8800aa\ndraw color #ff8800\ndraw color 1 2 3\n", &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "draw color 255 136 0 170\ndraw color 255 136 0 255\ndraw color 1 2 3 255");
+}
+
+#[test]
+fn comments_can_look_like_colors() {
+    let output = compile("comment", "#deadbeef\nlabel: #c0ffee\nprint \"x\" #c0ffee\nset c #c0ffee\n", &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "print \"x\"\nset c %c0ffee");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("#c0ffee comes after everything the instruction takes"));
+
+    // alpha can be left off, but it isn't a color, so a color after the rgb is still a comment
+    let output = compile("alpha-comment", "draw color 255 0 0 #ff0000\ndraw color 255 0 0 #ff0000 red\n", &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "draw color 255 0 0 255\ndraw color 255 0 0 255");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("#ff0000 can't go where alpha is"));
+}
+
+#[test]
+fn arrays_can_assign_to_keywords() {
+    let source = "array buf[4] in cell1\nregs r[2]\nset i 1\nmax = r[0]\ntarget = buf[i]\nline[1] = 5\nregs line[2]\n";
+    let output = compile("array", source, &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "set i 1\nset max r_0\nread target cell1 i\nset line_1 5");
+}
+
+#[test]
+fn register_indexes_are_clamped() {
+    let source = "regs r[3]\nr[0] = 10\nr[2] = 30\nset i 7\nx = r[i]\nset i -2\ny = r[i]\nr[i] = 5\nprint x\nprint \" \"\nprint y\nprint \" \"\nprint r_0\nprintflush message1\nend\n";
+    let output = compile("clamp", source, &["run"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8(output.stdout).unwrap().contains("30 10 5"));
+
+    // a bounds check jumps away instead, so there's nothing to clamp
+    let output = compile("checked", &format!("{}oob:\nend\n", source), &["--bounds-check", "oob"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("op max"));
+}
+
+#[test]
+fn routines_cant_loop() {
+    let output = compile("loop", "routine a:\n    gosub b\nendroutine\nroutine b:\n    gosub a\nendroutine\n", &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(":2: error: routines can't call each other in a loop: a -> b -> a"));
+
+    // routines can be named after keywords, which still have to be followed
+    let output = compile("keywords", "routine max:\n    gosub line\nendroutine\nroutine line:\n    gosub max\nendroutine\n", &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(":2: error: routines can't call each other in a loop: max -> line -> max"));
+}
+
+#[test]
+fn game_radar_lines_round_trip() {
+    // straight out of the game's export, bools and all
+    let exported = "radar enemy any any distance turret1 1 result\nuradar enemy ground any health 0 1 found\n";
+    let output = compile("radar-decompile", exported, &["decompile"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let decompiled = String::from_utf8(output.stdout).unwrap();
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("dropping"));
+
+    let output = compile("radar-recompile", &decompiled, &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), exported.trim());
+}